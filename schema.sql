@@ -7,17 +7,22 @@ CREATE TABLE cardset (
   parent TEXT,
   created DATETIME DEFAULT CURRENT_TIMESTAMP,
   subject TEXT NOT NULL,
+  algorithm TEXT,
+  remix_of TEXT,
   FOREIGN KEY (subject) REFERENCES subject (id),
-  FOREIGN KEY (parent) REFERENCES folder (id)
+  FOREIGN KEY (parent) REFERENCES folder (id),
+  FOREIGN KEY (remix_of) REFERENCES cardset (id)
 );
 
 CREATE TABLE folder (
   id TEXT PRIMARY KEY,
   title TEXT NOT NULL,
   description TEXT NOT NULL,
+  parent TEXT,
   created DATETIME DEFAULT CURRENT_TIMESTAMP,
   subject TEXT NOT NULL,
-  FOREIGN KEY (subject) REFERENCES subject (id)
+  FOREIGN KEY (subject) REFERENCES subject (id),
+  FOREIGN KEY (parent) REFERENCES folder (id)
 );
 
 CREATE TABLE subject (
@@ -33,13 +38,24 @@ INSERT INTO subject (id, name, color) VALUES
 ('spanish', 'spanish', 'yellow');
 
 CREATE TABLE card (
-  id INTEGER PRIMARY KEY,
+  id TEXT PRIMARY KEY,
   term TEXT NOT NULL,
   definition TEXT NOT NULL,
-  cardset INTEGER NOT NULL,
+  cardset TEXT NOT NULL,
+  position INTEGER NOT NULL,
   FOREIGN KEY (cardset) REFERENCES cardset (id)
 );
 
+CREATE TABLE review (
+  id INTEGER PRIMARY KEY,
+  cardset TEXT NOT NULL,
+  -- the card's id, followed by `/reverse` or `/c{n}` for its other items
+  card TEXT NOT NULL,
+  rating TEXT NOT NULL,
+  response_time INTEGER NOT NULL,
+  created INTEGER NOT NULL
+);
+
 COMMIT;
 
 -- # vim: tabstop=2 shiftwidth=2
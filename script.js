@@ -27,3 +27,34 @@ observer.observe(document.body, {
   subtree: true,
 });
 applyEvents();
+
+// sends the file picked in an upload form as the body of a POST to `url`, with
+// the form's other fields in the query string, and shows the response below it
+function uploadFile(form, url) {
+  const data = new FormData(form);
+  const file = data.get("file");
+  const report = form.querySelector(".upload-report");
+  data.delete("file");
+  data.set("filename", file.name);
+  if (!(file instanceof File) || !file.name) {
+    report.textContent = "pick a file first";
+    return;
+  }
+  report.textContent = "importing...";
+  fetch(`${url}?${new URLSearchParams(data)}`, { method: "POST", body: file })
+    .then((response) => response.text())
+    .then((html) => {
+      report.innerHTML = html;
+    })
+    .catch(() => {
+      report.textContent = "the import didn't work (is a subject picked?)";
+    });
+}
+
+// running clocks, e.g. in the matching game
+setInterval(() => {
+  for (const clock of document.querySelectorAll("[data-started]")) {
+    const seconds = (Date.now() - Number(clock.dataset.started)) / 1000;
+    clock.textContent = `${seconds.toFixed(1)}s`;
+  }
+}, 100);
@@ -0,0 +1,493 @@
+//! Anki packages (`.apkg`): a zip holding the collection as an SQLite database
+//! and the media the notes use. Importing turns each deck into a set (with its
+//! parent decks as folders), each note into a card and stores the media;
+//! exporting does the reverse (see [`export`]).
+use crate::components;
+use crate::data::{self, body_to_bytes, Card, Folder, Query, ResourceError, Subject};
+use crate::editor;
+use crate::import::Report;
+use crate::media;
+use crate::store::Store;
+use html_builder::prelude::*;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Request, Response};
+use itertools::Itertools;
+use libsql::Builder;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{Cursor, Read};
+use uuid::Uuid;
+use zip::{result::ZipError, ZipArchive};
+
+pub mod export;
+
+/// Separates a note's fields in `notes.flds`.
+const FIELD_SEPARATOR: char = '\x1f';
+
+/// A note type, from the JSON in `col.models`.
+#[derive(Clone, Debug, Deserialize)]
+struct Model {
+    name: String,
+    /// 0 for standard note types, 1 for cloze.
+    #[serde(rename = "type", default)]
+    kind: u8,
+    flds: Vec<serde_json::Value>,
+}
+
+/// A deck, from the JSON in `col.decks`.
+#[derive(Clone, Debug, Deserialize)]
+struct Deck {
+    name: String,
+}
+
+#[derive(Clone, Debug)]
+struct Note {
+    guid: String,
+    model: String,
+    deck: Option<String>,
+    fields: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+struct Collection {
+    models: HashMap<String, Model>,
+    decks: HashMap<String, Deck>,
+    notes: Vec<Note>,
+}
+
+/// The collection and the media (by file name) in a package.
+fn unzip(bytes: Vec<u8>) -> Result<(Vec<u8>, Vec<(String, Vec<u8>)>), ResourceError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let collection = match entry(&mut archive, "collection.anki21")? {
+        Some(collection) => collection,
+        None if entry(&mut archive, "collection.anki21b")?.is_some() => {
+            return Err(ResourceError::Custom(
+                "this package is in Anki's newest format, which can't be read yet. export it \
+                 again with \"support older Anki versions\" ticked"
+                    .to_string(),
+            ))
+        }
+        None => entry(&mut archive, "collection.anki2")?.ok_or_else(|| {
+            ResourceError::Custom("this doesn't look like an Anki package".to_string())
+        })?,
+    };
+    let names = match entry(&mut archive, "media")? {
+        Some(names) => serde_json::from_slice::<HashMap<String, String>>(&names)?,
+        None => HashMap::new(),
+    };
+    let mut media = Vec::new();
+    for (number, name) in names {
+        if let Some(bytes) = entry(&mut archive, &number)? {
+            media.push((name, bytes));
+        }
+    }
+    Ok((collection, media))
+}
+
+fn entry(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    name: &str,
+) -> Result<Option<Vec<u8>>, ResourceError> {
+    let mut file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// Reads the collection, which has to be written to a file for libsql to open it.
+async fn read_collection(bytes: &[u8]) -> Result<Collection, ResourceError> {
+    let path = std::env::temp_dir().join(format!("flopcards-import-{}.anki2", Uuid::new_v4()));
+    fs::write(&path, bytes)?;
+    let collection = query_collection(&path.to_string_lossy()).await;
+    fs::remove_file(&path)?;
+    collection
+}
+
+async fn query_collection(path: &str) -> Result<Collection, ResourceError> {
+    let connection = Builder::new_local(path).build().await?.connect()?;
+    let mut rows = connection
+        .query("SELECT models, decks FROM col", ())
+        .await?;
+    let row = rows
+        .next()
+        .await?
+        .ok_or_else(|| ResourceError::Custom("the collection is empty".to_string()))?;
+    let models = serde_json::from_str(&row.get::<String>(0)?)?;
+    let decks = serde_json::from_str(&row.get::<String>(1)?)?;
+    // a note's first card decides its deck
+    let mut rows = connection
+        .query(
+            "SELECT guid, mid, flds, \
+             (SELECT did FROM cards WHERE cards.nid = notes.id ORDER BY ord LIMIT 1) \
+             FROM notes ORDER BY id",
+            (),
+        )
+        .await?;
+    let mut notes = Vec::new();
+    while let Some(row) = rows.next().await? {
+        notes.push(Note {
+            guid: row.get(0)?,
+            model: row.get::<i64>(1)?.to_string(),
+            deck: row.get::<Option<i64>>(3)?.map(|deck| deck.to_string()),
+            fields: row
+                .get::<String>(2)?
+                .split(FIELD_SEPARATOR)
+                .map(ToString::to_string)
+                .collect(),
+        });
+    }
+    Ok(Collection {
+        models,
+        decks,
+        notes,
+    })
+}
+
+/// The note as a card, or `None` if its type isn't supported or a side is empty.
+/// `media` maps the names of the package's files to the names they were stored
+/// under.
+fn card(
+    note: &Note,
+    model: &Model,
+    media: &HashMap<String, String>,
+    report: &mut Report,
+) -> Option<Card> {
+    let field = |index: usize| {
+        note.fields
+            .get(index)
+            .map_or_else(String::new, |field| text(field, media))
+    };
+    // cloze notes keep their `{{c1::...}}` markers, which flopcards reads the same way
+    let supported = match model.kind {
+        1 => true,
+        _ => model.flds.len() >= 2,
+    };
+    if !supported {
+        report.skip(format!(
+            "notes of the unsupported note type \"{}\"",
+            model.name
+        ));
+        return None;
+    }
+    let card = Card {
+        id: Uuid::new_v5(
+            &Uuid::NAMESPACE_OID,
+            format!("anki\n{}", note.guid).as_bytes(),
+        )
+        .to_string(),
+        term: field(0),
+        definition: field(1),
+    };
+    let empty = if model.kind == 1 {
+        !card.is_cloze()
+    } else {
+        card.term.is_empty() || card.definition.is_empty()
+    };
+    if empty {
+        report.skip("notes with nothing on one side");
+        return None;
+    }
+    Some(card)
+}
+
+/// Imports the package into the subject: a set per deck with cards, inside folders
+/// for the decks above it.
+pub async fn import(
+    bytes: Vec<u8>,
+    subject: &Subject,
+    store: &dyn Store,
+) -> Result<Report, ResourceError> {
+    let (collection, files) = unzip(bytes)?;
+    let collection = read_collection(&collection).await?;
+    let mut report = Report::default();
+    let mut media = HashMap::new();
+    for (name, bytes) in files {
+        let stored = media::save(&name, &bytes)?;
+        media.insert(name, stored);
+        report.media += 1;
+    }
+    let mut decks = BTreeMap::<String, Vec<Card>>::new();
+    for note in &collection.notes {
+        let Some(model) = collection.models.get(&note.model) else {
+            report.skip(format!("notes of an unknown note type ({})", note.model));
+            continue;
+        };
+        let Some(card) = card(note, model, &media, &mut report) else {
+            continue;
+        };
+        let deck = note
+            .deck
+            .as_ref()
+            .and_then(|deck| collection.decks.get(deck))
+            .map_or("Default", |deck| deck.name.as_str());
+        decks.entry(deck.to_string()).or_default().push(card);
+    }
+    for (deck, cards) in decks {
+        let mut names = deck.split("::").collect::<Vec<_>>();
+        let title = names.pop().unwrap_or_default();
+        let description = format!("imported from the Anki deck {deck}");
+        report
+            .save_set(store, subject, &names, title, description, cards)
+            .await?;
+    }
+    Ok(report)
+}
+
+/// A field's HTML as plain text, with line breaks kept, images written as
+/// `![](/media/{name})` and media renamed to the names in `media`.
+fn text(html: &str, media: &HashMap<String, String>) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode(&rest[..start]));
+        let Some(end) = rest[start..].find('>') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        let name = tag
+            .split(|character: char| character.is_whitespace() || character == '/')
+            .find(|name| !name.is_empty())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match name.as_str() {
+            "br" => text.push('\n'),
+            "div" | "p" | "li" | "tr" if !tag.starts_with('/') => {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+            }
+            "img" => {
+                if let Some(source) = attribute(tag, "src") {
+                    text.push_str(&format!("![](/media/{})", stored(&decode(source), media)));
+                }
+            }
+            _ => {}
+        }
+        rest = &rest[start + end + 1..];
+    }
+    text.push_str(&decode(rest));
+    sounds(&text, media)
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Renames the files in `[sound:...]` tags to the names they are stored under.
+fn sounds(text: &str, media: &HashMap<String, String>) -> String {
+    let mut renamed = String::new();
+    let mut rest = text;
+    while let Some((before, name, after)) = media::sound(rest) {
+        renamed.push_str(before);
+        renamed.push_str(&format!("[sound:{}]", stored(name, media)));
+        rest = after;
+    }
+    renamed.push_str(rest);
+    renamed
+}
+
+/// The name a file in the package was stored under, or a safe version of its name
+/// if the package didn't have it.
+fn stored(name: &str, media: &HashMap<String, String>) -> String {
+    media
+        .get(name)
+        .cloned()
+        .unwrap_or_else(|| media::file_name(name))
+}
+
+/// The value of an attribute in the inside of a tag, quoted or not.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let lowercase = tag.to_ascii_lowercase();
+    let start = lowercase
+        .match_indices(name)
+        .map(|(index, _)| index)
+        .find(|index| {
+            lowercase[..*index].ends_with(char::is_whitespace)
+                && lowercase[index + name.len()..]
+                    .trim_start()
+                    .starts_with('=')
+        })?;
+    let value = tag[start + name.len()..].trim_start()[1..].trim_start();
+    match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next(),
+        _ => value.split(char::is_whitespace).next(),
+    }
+}
+
+/// Replaces HTML entities with the characters they stand for.
+fn decode(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let entity = &rest[start + 1..];
+        let character = entity.find(';').and_then(|end| {
+            let character = match &entity[..end] {
+                "nbsp" => ' ',
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                number => {
+                    let number = number.strip_prefix('#')?;
+                    let code = match number.strip_prefix(['x', 'X']) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => number.parse().ok()?,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((character, end))
+        });
+        if let Some((character, end)) = character {
+            decoded.push(character);
+            rest = &entity[end + 1..];
+        } else {
+            decoded.push('&');
+            rest = entity;
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// `/import/anki`.
+pub async fn page(store: &dyn Store) -> Result<Html, ResourceError> {
+    let subjects = store.subjects().await?;
+    Ok(components::page(
+        "import from Anki - flopcards",
+        body()
+            .child(h1("Import from Anki"))
+            .child(p(
+                "each deck becomes a set, inside folders named after the decks above it",
+            ))
+            .child(components::upload_form(
+                "anki",
+                "/import/anki",
+                &subjects,
+                None,
+            )),
+    ))
+}
+
+/// Handles `POST /import/anki?subject=`, whose body is the package, answering with
+/// the import report. Problems with the package are shown in its place.
+pub async fn upload(
+    request: Request<hyper::body::Incoming>,
+    store: &dyn Store,
+) -> Result<Div, ResourceError> {
+    let query = Query::from_request(&request);
+    let subject = query.get("subject")?;
+    let subject = store
+        .subjects()
+        .await?
+        .into_iter()
+        .find(|found| found.id == subject)
+        .ok_or_else(|| ResourceError::NotFound(format!("/?subject={subject}")))?;
+    let bytes = body_to_bytes(request).await?;
+    match import(bytes, &subject, store).await {
+        Ok(report) => Ok(components::import_report(&report)),
+        Err(error @ (ResourceError::Custom(_) | ResourceError::Zip(_))) => {
+            Ok(div().class("text-red-500").child(p(error.to_string())))
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// `/sets/{id}/export.apkg`: the set as a deck of its own.
+pub async fn export_set(
+    id: &str,
+    store: &dyn Store,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let set = store.get_set(id).await?;
+    let name = editor::slug(&set.title);
+    let deck = deck_name([set.title.as_str()]);
+    data::attachment(
+        &format!("{name}.apkg"),
+        "application/octet-stream",
+        export::package(&[(deck, set)], store).await?,
+    )
+}
+
+/// `/subjects/{id}/export.apkg`: a deck for the subject with a deck inside it for
+/// each set, and for each folder the sets are in.
+pub async fn export_subject(
+    id: &str,
+    store: &dyn Store,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let subject = store
+        .subjects()
+        .await?
+        .into_iter()
+        .find(|subject| subject.id == id)
+        .ok_or_else(|| ResourceError::NotFound(format!("/subjects/{id}/export.apkg")))?;
+    let folders = store.folders(id).await?;
+    let decks = store
+        .sets(id)
+        .await?
+        .into_iter()
+        .map(|set| {
+            let folders = set
+                .folder
+                .as_deref()
+                .map(|folder| Folder::breadcrumbs(&folders, folder))
+                .unwrap_or_default();
+            let deck = deck_name(
+                std::iter::once(subject.name.as_str())
+                    .chain(folders.iter().map(|folder| folder.title.as_str()))
+                    .chain([set.title.as_str()]),
+            );
+            (deck, set)
+        })
+        .collect::<Vec<_>>();
+    data::attachment(
+        &format!("{}.apkg", subject.id),
+        "application/octet-stream",
+        export::package(&decks, store).await?,
+    )
+}
+
+/// The `Parent::Child` name of the deck at the end of `path`, keeping any `::`
+/// in the names themselves from nesting it further.
+fn deck_name<'a>(path: impl IntoIterator<Item = &'a str>) -> String {
+    path.into_iter()
+        .map(|name| name.replace("::", ":"))
+        .join("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_points_media_at_the_names_they_were_stored_under() {
+        let media = HashMap::from([
+            (
+                "cell.png".to_string(),
+                "0123456789abcdef-cell.png".to_string(),
+            ),
+            (
+                "say it.mp3".to_string(),
+                "fedcba9876543210-say-it.mp3".to_string(),
+            ),
+        ]);
+        assert_eq!(
+            text(
+                "<div>mitochondria&nbsp;<img src=\"cell.png\"></div>[sound:say it.mp3]<br>[sound:gone.mp3]",
+                &media
+            ),
+            "mitochondria ![](/media/0123456789abcdef-cell.png)\
+             [sound:fedcba9876543210-say-it.mp3]\n[sound:gone.mp3]"
+        );
+    }
+}
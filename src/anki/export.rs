@@ -0,0 +1,535 @@
+//! Writing sets out as an Anki package. The collection uses the older layout in
+//! `schema.sql`, which AnkiDroid and every desktop version can import.
+use super::FIELD_SEPARATOR;
+use crate::cloze;
+use crate::data::{Card, Rating, ResourceError, Set, Side};
+use crate::media;
+use crate::scheduler::{self, Algorithm, Progress, ReviewLog, ReviewState, DAY};
+use crate::store::Store;
+use libsql::{params, Builder, Connection};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::{Cursor, Write};
+use uuid::Uuid;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+const SCHEMA: &str = include_str!("schema.sql");
+
+/// Fixed so that exporting again updates the same note types in Anki.
+const BASIC_MODEL: i64 = 1_600_000_000_001;
+const CLOZE_MODEL: i64 = 1_600_000_000_002;
+const DEFAULT_DECK: i64 = 1;
+
+/// The longest answer Anki records, in milliseconds.
+const MAX_ANSWER_TIME: u64 = 60_000;
+
+/// Reviews from the log, keyed by set id and review id.
+type Reviews = HashMap<(String, String), Vec<ReviewLog>>;
+
+/// Where a card stands in Anki's scheduler.
+struct Schedule {
+    /// 0 for new cards, 2 for cards in review.
+    kind: i64,
+    /// The position of a new card, or the day a review is due counted from the
+    /// collection's creation.
+    due: i64,
+    interval: i64,
+    /// The ease in thousandths.
+    factor: i64,
+}
+
+struct Writer<'a> {
+    connection: Connection,
+    progress: &'a Progress,
+    reviews: &'a Reviews,
+    /// The start of today, which due days are counted from.
+    created: i64,
+    modified: i64,
+    last_id: i64,
+    decks: BTreeMap<String, i64>,
+    media: BTreeSet<String>,
+    new_cards: i64,
+    review_ids: HashSet<i64>,
+}
+
+impl Writer<'_> {
+    /// Notes, cards and decks all get ids from the same counter, so they never clash.
+    fn id(&mut self) -> i64 {
+        self.last_id += 1;
+        self.last_id
+    }
+
+    /// The deck's id, adding it and any decks above it if they aren't there yet.
+    fn deck(&mut self, name: &str) -> i64 {
+        let mut id = DEFAULT_DECK;
+        let mut path = String::new();
+        for part in name.split("::") {
+            if !path.is_empty() {
+                path.push_str("::");
+            }
+            path.push_str(part);
+            id = if let Some(existing) = self.decks.get(&path).copied() {
+                existing
+            } else {
+                let added = self.id();
+                self.decks.insert(path.clone(), added);
+                added
+            };
+        }
+        id
+    }
+
+    /// The text as the HTML Anki keeps in fields, noting any media it uses.
+    fn field(&mut self, text: &str) -> String {
+        let mut html = String::new();
+        let mut rest = text;
+        while let Some((before, alt, source, after)) = media::image(rest) {
+            html.push_str(&escape(before));
+            let source = source.strip_prefix("/media/").map_or(source, |name| {
+                self.media.insert(name.to_string());
+                name
+            });
+            html.push_str(&format!(
+                "<img src=\"{}\" alt=\"{}\">",
+                escape(source),
+                escape(alt)
+            ));
+            rest = after;
+        }
+        html.push_str(&escape(rest));
+        let mut rest = text;
+        while let Some((_, name, after)) = media::sound(rest) {
+            self.media.insert(name.to_string());
+            rest = after;
+        }
+        html
+    }
+
+    fn schedule(&mut self, state: &ReviewState) -> Schedule {
+        if state.is_new() {
+            self.new_cards += 1;
+            return Schedule {
+                kind: 0,
+                due: self.new_cards,
+                interval: 0,
+                factor: 0,
+            };
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let (interval, factor) = (
+            (state.interval.round() as i64).max(1),
+            (state.ease * 1000.0).round() as i64,
+        );
+        Schedule {
+            kind: 2,
+            due: (state.due - self.created).div_euclid(DAY),
+            interval,
+            factor,
+        }
+    }
+
+    /// Adds the card as a note, with an Anki card for each way it is studied.
+    async fn note(&mut self, deck: i64, set: &Set, card: &Card) -> Result<(), ResourceError> {
+        let note = self.id();
+        // each Anki card's template number and the id its review state is kept under
+        let (model, fields, cards) = if card.is_cloze() {
+            let cards = cloze::numbers(&card.term)
+                .into_iter()
+                .filter(|number| *number > 0)
+                .map(|number| (i64::from(number - 1), cloze::item_id(&card.id, number)))
+                .collect::<Vec<_>>();
+            let fields = vec![self.field(&card.term), self.field(&card.definition)];
+            (CLOZE_MODEL, fields, cards)
+        } else {
+            let reverse = card.review_id(Side::Definition);
+            let reversed = !self.progress.get(&set.id, &reverse).is_new();
+            let mut cards = vec![(0, card.review_id(Side::Term))];
+            if reversed {
+                cards.push((1, reverse));
+            }
+            let fields = vec![
+                self.field(&card.term),
+                self.field(&card.definition),
+                if reversed { "y" } else { "" }.to_string(),
+            ];
+            (BASIC_MODEL, fields, cards)
+        };
+        // the fields already use the names the media is stored under
+        let sort = super::text(&fields[0], &HashMap::new());
+        let hash = sha1_smol::Sha1::from(&sort).digest().bytes();
+        let checksum = u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]]);
+        self.connection
+            .execute(
+                "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, '', ?5, ?6, ?7, 0, '')",
+                params![
+                    note,
+                    card.id.as_str(),
+                    model,
+                    self.modified,
+                    fields.join(&FIELD_SEPARATOR.to_string()),
+                    sort,
+                    i64::from(checksum)
+                ],
+            )
+            .await?;
+        for (template, review_id) in cards {
+            let id = self.id();
+            let state = self.progress.get(&set.id, &review_id);
+            let schedule = self.schedule(&state);
+            self.connection
+                .execute(
+                    "INSERT INTO cards \
+                     VALUES (?1, ?2, ?3, ?4, ?5, -1, ?6, ?6, ?7, ?8, ?9, ?10, ?11, 0, 0, 0, 0, '')",
+                    params![
+                        id,
+                        note,
+                        deck,
+                        template,
+                        self.modified,
+                        schedule.kind,
+                        schedule.due,
+                        schedule.interval,
+                        schedule.factor,
+                        i64::from(state.repetitions),
+                        i64::from(state.lapses)
+                    ],
+                )
+                .await?;
+            let algorithm = set.algorithm.unwrap_or(self.progress.algorithm);
+            self.history(id, &set.id, &review_id, algorithm).await?;
+        }
+        Ok(())
+    }
+
+    /// Adds the card's reviews to Anki's review log. The intervals and ease Anki
+    /// keeps with each review come from replaying the card's reviews through
+    /// `algorithm`, with today's settings.
+    async fn history(
+        &mut self,
+        card: i64,
+        set: &str,
+        review_id: &str,
+        algorithm: Algorithm,
+    ) -> Result<(), ResourceError> {
+        let Some(reviews) = self.reviews.get(&(set.to_string(), review_id.to_string())) else {
+            return Ok(());
+        };
+        let mut state = ReviewState::default();
+        // whether the card has been passed yet, and whether it was failed last time
+        let (mut learnt, mut forgotten) = (false, false);
+        for review in reviews {
+            let ease = match review.rating {
+                Rating::Terrible | Rating::Bad => 1,
+                Rating::Ok => 2,
+                Rating::Good => 3,
+                Rating::Perfect => 4,
+            };
+            // the log is keyed by when the review happened, in milliseconds
+            let mut id = review.timestamp * 1000;
+            while !self.review_ids.insert(id) {
+                id += 1;
+            }
+            let time = i64::try_from(review.response_time.min(MAX_ANSWER_TIME)).unwrap_or_default();
+            // learning, review or relearning, from before this review
+            let kind = match (learnt, forgotten) {
+                (false, _) => 0,
+                (true, false) => 1,
+                (true, true) => 2,
+            };
+            let last_interval = state.interval;
+            state = self
+                .progress
+                .review(algorithm, state, review.rating, review.timestamp);
+            let failed = ease == 1;
+            learnt |= !failed;
+            forgotten = failed;
+            #[allow(clippy::cast_possible_truncation)]
+            let (interval, last_interval, factor) = (
+                (state.interval.round() as i64).max(1),
+                last_interval.round() as i64,
+                (state.ease * 1000.0).round() as i64,
+            );
+            self.connection
+                .execute(
+                    "INSERT INTO revlog VALUES (?1, ?2, -1, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    params![id, card, ease, interval, last_interval, factor, time, kind],
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the collection's settings, note types and decks.
+    async fn finish(&self) -> Result<(), ResourceError> {
+        let mut models = Map::new();
+        let mut basic = model(
+            BASIC_MODEL,
+            "flopcards",
+            0,
+            &["Term", "Definition", "Add Reverse"],
+            &[
+                ("Term first", "{{Term}}", "{{Definition}}"),
+                (
+                    "Definition first",
+                    "{{#Add Reverse}}{{Definition}}{{/Add Reverse}}",
+                    "{{Term}}",
+                ),
+            ],
+            self.modified,
+        );
+        // the term-first card needs a term, the other a definition and "Add Reverse"
+        basic["req"] = json!([[0, "any", [0]], [1, "all", [1, 2]]]);
+        models.insert(BASIC_MODEL.to_string(), basic);
+        models.insert(
+            CLOZE_MODEL.to_string(),
+            model(
+                CLOZE_MODEL,
+                "flopcards cloze",
+                1,
+                &["Text", "Back Extra"],
+                &[("Cloze", "{{cloze:Text}}", "{{Back Extra}}")],
+                self.modified,
+            ),
+        );
+        let mut decks = Map::new();
+        decks.insert(
+            DEFAULT_DECK.to_string(),
+            deck(DEFAULT_DECK, "Default", self.modified),
+        );
+        for (name, id) in &self.decks {
+            decks.insert(id.to_string(), deck(*id, name, self.modified));
+        }
+        let conf = json!({
+            "nextPos": self.new_cards + 1,
+            "estTimes": true,
+            "activeDecks": [DEFAULT_DECK],
+            "sortType": "noteFld",
+            "timeLim": 0,
+            "sortBackwards": false,
+            "addToCur": true,
+            "curDeck": DEFAULT_DECK,
+            "newBury": true,
+            "newSpread": 0,
+            "dueCounts": true,
+            "curModel": BASIC_MODEL.to_string(),
+            "collapseTime": 1200,
+        });
+        self.connection
+            .execute(
+                "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+                params![
+                    self.created,
+                    self.modified * 1000,
+                    conf.to_string(),
+                    Value::Object(models).to_string(),
+                    Value::Object(decks).to_string(),
+                    json!({ DEFAULT_DECK.to_string(): deck_options() }).to_string()
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Escapes the text for a field, turning line breaks into `<br>`.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "<br>")
+}
+
+/// A note type with the given fields and templates, each template being a name
+/// and what goes on the front and back.
+fn model(
+    id: i64,
+    name: &str,
+    kind: u8,
+    fields: &[&str],
+    templates: &[(&str, &str, &str)],
+    modified: i64,
+) -> Value {
+    let fields = fields
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({
+                "name": name,
+                "ord": ord,
+                "sticky": false,
+                "rtl": false,
+                "font": "Arial",
+                "size": 20,
+                "media": [],
+            })
+        })
+        .collect::<Vec<_>>();
+    let templates = templates
+        .iter()
+        .enumerate()
+        .map(|(ord, (name, front, back))| {
+            json!({
+                "name": name,
+                "ord": ord,
+                "qfmt": front,
+                "afmt": format!("{{{{FrontSide}}}}\n\n<hr id=answer>\n\n{back}"),
+                "did": null,
+                "bqfmt": "",
+                "bafmt": "",
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "id": id,
+        "name": name,
+        "type": kind,
+        "mod": modified,
+        "usn": -1,
+        "sortf": 0,
+        "did": DEFAULT_DECK,
+        "flds": fields,
+        "tmpls": templates,
+        "css": ".card { font-family: arial; font-size: 20px; text-align: center; }",
+        "latexPre": "\\documentclass[12pt]{article}\n\\special{papersize=3in,5in}\n\\usepackage{amssymb,amsmath}\n\\pagestyle{empty}\n\\setlength{\\parindent}{0in}\n\\begin{document}\n",
+        "latexPost": "\\end{document}",
+        "tags": [],
+        "vers": [],
+    })
+}
+
+fn deck(id: i64, name: &str, modified: i64) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "mod": modified,
+        "usn": -1,
+        "lrnToday": [0, 0],
+        "revToday": [0, 0],
+        "newToday": [0, 0],
+        "timeToday": [0, 0],
+        "collapsed": false,
+        "browserCollapsed": false,
+        "desc": "",
+        "dyn": 0,
+        "conf": 1,
+        "extendNew": 0,
+        "extendRev": 0,
+    })
+}
+
+/// Anki's default deck options.
+fn deck_options() -> Value {
+    json!({
+        "id": 1,
+        "name": "Default",
+        "mod": 0,
+        "usn": 0,
+        "maxTaken": 60,
+        "autoplay": true,
+        "timer": 0,
+        "replayq": true,
+        "dyn": false,
+        "new": {
+            "bury": false,
+            "delays": [1.0, 10.0],
+            "initialFactor": 2500,
+            "ints": [1, 4, 0],
+            "order": 1,
+            "perDay": 20,
+        },
+        "rev": {
+            "bury": false,
+            "ease4": 1.3,
+            "ivlFct": 1.0,
+            "maxIvl": 36500,
+            "perDay": 200,
+            "hardFactor": 1.2,
+        },
+        "lapse": {
+            "delays": [10.0],
+            "leechAction": 1,
+            "leechFails": 8,
+            "minInt": 1,
+            "mult": 0.0,
+        },
+    })
+}
+
+/// Writes the collection to `path`, returning the names of the media its cards use.
+async fn write_collection(
+    path: &str,
+    decks: &[(String, Set)],
+    progress: &Progress,
+    reviews: &Reviews,
+) -> Result<BTreeSet<String>, ResourceError> {
+    let connection = Builder::new_local(path).build().await?.connect()?;
+    connection.execute_batch(SCHEMA).await?;
+    connection.execute("BEGIN", ()).await?;
+    let now = scheduler::now();
+    let mut writer = Writer {
+        connection,
+        progress,
+        reviews,
+        created: now - now.rem_euclid(DAY),
+        modified: now,
+        last_id: scheduler::now_millis(),
+        decks: BTreeMap::new(),
+        media: BTreeSet::new(),
+        new_cards: 0,
+        review_ids: HashSet::new(),
+    };
+    for (deck, set) in decks {
+        let deck = writer.deck(deck);
+        for card in &set.cards {
+            writer.note(deck, set, card).await?;
+        }
+    }
+    writer.finish().await?;
+    writer.connection.execute("COMMIT", ()).await?;
+    Ok(writer.media)
+}
+
+/// Zips the collection with the media it uses. Media that has been deleted since
+/// is left out.
+fn zip(collection: &[u8], media: &BTreeSet<String>) -> Result<Vec<u8>, ResourceError> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("collection.anki2", options)?;
+    zip.write_all(collection)?;
+    let mut names = Map::new();
+    for name in media {
+        let bytes = match media::read(name) {
+            Ok((bytes, _)) => bytes,
+            Err(ResourceError::NotFound(_)) => continue,
+            Err(error) => return Err(error),
+        };
+        // media is stored under numbers, with a file mapping them back to names
+        let number = names.len().to_string();
+        zip.start_file(number.as_str(), options)?;
+        zip.write_all(&bytes)?;
+        names.insert(number, Value::String(name.clone()));
+    }
+    zip.start_file("media", options)?;
+    zip.write_all(Value::Object(names).to_string().as_bytes())?;
+    Ok(zip.finish()?.into_inner())
+}
+
+/// An Anki package with a deck for each set, named with the `Parent::Child` name
+/// paired with it. Cards that have been studied keep their schedule and history.
+pub async fn package(decks: &[(String, Set)], store: &dyn Store) -> Result<Vec<u8>, ResourceError> {
+    let progress = Progress::load()?;
+    let mut reviews = Reviews::new();
+    for review in store.reviews().await? {
+        reviews
+            .entry((review.set.clone(), review.card.clone()))
+            .or_default()
+            .push(review);
+    }
+    let path = std::env::temp_dir().join(format!("flopcards-export-{}.anki2", Uuid::new_v4()));
+    let media = write_collection(&path.to_string_lossy(), decks, &progress, &reviews).await;
+    let collection = fs::read(&path);
+    fs::remove_file(&path)?;
+    zip(&collection?, &media?)
+}
@@ -0,0 +1,27 @@
+//! Imports the TOML sets in `flashcards/` into a database.
+//!
+//! Usage: `cargo run --bin migrate -- <database file> [flashcards directory]`
+//!
+//! A `libsql://` URL can be given instead of a file to import into a database
+//! hosted by Turso, with its token in `TURSO_TOKEN`.
+use flopcards::data::ResourceError;
+use flopcards::database::Database;
+use flopcards::migrate::migrate;
+
+#[tokio::main]
+async fn main() -> Result<(), ResourceError> {
+    let mut args = std::env::args().skip(1);
+    let Some(database) = args.next() else {
+        eprintln!("usage: migrate <database file> [flashcards directory]");
+        std::process::exit(1);
+    };
+    let root = args.next().unwrap_or_else(|| "./flashcards".to_string());
+    let database = if database.starts_with("libsql://") {
+        let token = std::env::var("TURSO_TOKEN").unwrap_or_default();
+        Database::open_remote(&database, &token).await?
+    } else {
+        Database::open_local(&database).await?
+    };
+    print!("{}", migrate(root, &database).await?);
+    Ok(())
+}
@@ -0,0 +1,170 @@
+//! Cloze deletions: text with markers like `{{c1::subduction}}` or
+//! `{{c2::mantle::layer}}` (with a hint), studied as one item per cloze number
+//! with that number's words blanked out.
+use std::collections::BTreeSet;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Part<'a> {
+    Text(&'a str),
+    Cloze {
+        number: u32,
+        answer: &'a str,
+        hint: Option<&'a str>,
+    },
+}
+
+/// Splits text into plain text and clozes. Anything that looks like a marker but
+/// isn't one (such as `{{c::x}}` or an unclosed `{{c1::`) is left as text.
+pub fn parse(text: &str) -> Vec<Part<'_>> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{c") {
+        let marker = &rest[start + 3..];
+        let Some((digits, number, cloze)) = marker.split_once("::").and_then(|(digits, cloze)| {
+            Some((
+                digits,
+                digits.parse::<u32>().ok()?,
+                &cloze[..cloze.find("}}")?],
+            ))
+        }) else {
+            parts.push(Part::Text(&rest[..start + 3]));
+            rest = marker;
+            continue;
+        };
+        if start > 0 {
+            parts.push(Part::Text(&rest[..start]));
+        }
+        let (answer, hint) = cloze
+            .split_once("::")
+            .map_or((cloze, None), |(answer, hint)| (answer, Some(hint)));
+        parts.push(Part::Cloze {
+            number,
+            answer,
+            hint,
+        });
+        // skip "{{c", the number, "::", the cloze and "}}"
+        rest = &marker[digits.len() + 2 + cloze.len() + 2..];
+    }
+    if !rest.is_empty() {
+        parts.push(Part::Text(rest));
+    }
+    parts
+}
+
+/// The cloze numbers in the text, in order.
+pub fn numbers(text: &str) -> BTreeSet<u32> {
+    parse(text)
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Cloze { number, .. } => Some(number),
+            Part::Text(_) => None,
+        })
+        .collect()
+}
+
+/// The text with cloze `number` blanked out (showing its hint, if it has one) and
+/// every other cloze filled in, and the words that were blanked.
+pub fn render(text: &str, number: u32) -> (String, String) {
+    let mut prompt = String::new();
+    let mut answers = Vec::new();
+    for part in parse(text) {
+        match part {
+            Part::Text(text) => prompt.push_str(text),
+            Part::Cloze {
+                number: cloze,
+                answer,
+                hint,
+            } if cloze == number => {
+                prompt.push_str(&format!("[{}]", hint.unwrap_or("...")));
+                answers.push(answer.trim());
+            }
+            Part::Cloze { answer, .. } => prompt.push_str(answer),
+        }
+    }
+    (prompt, answers.join(", "))
+}
+
+/// The id review state is kept under for one cloze of a card.
+pub fn item_id(card: &str, number: u32) -> String {
+    format!("{card}/c{number}")
+}
+
+/// Whether `id` is one made by [`item_id`].
+pub fn is_item_id(id: &str) -> bool {
+    id.rsplit_once("/c")
+        .is_some_and(|(_, number)| number.parse::<u32>().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_splits_text_and_clozes() {
+        assert_eq!(
+            parse("plates meet at {{c1::subduction}} zones in the {{c2::mantle::layer}}"),
+            [
+                Part::Text("plates meet at "),
+                Part::Cloze {
+                    number: 1,
+                    answer: "subduction",
+                    hint: None,
+                },
+                Part::Text(" zones in the "),
+                Part::Cloze {
+                    number: 2,
+                    answer: "mantle",
+                    hint: Some("layer"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_leaves_broken_markers_as_text() {
+        let text = |parts: Vec<Part>| {
+            parts
+                .into_iter()
+                .map(|part| match part {
+                    Part::Text(text) => text,
+                    Part::Cloze { .. } => panic!("expected only text"),
+                })
+                .collect::<String>()
+        };
+        assert_eq!(
+            text(parse("{{c::x}} and {{cx::y}}")),
+            "{{c::x}} and {{cx::y}}"
+        );
+        assert_eq!(text(parse("unclosed {{c1::core")), "unclosed {{c1::core");
+        assert_eq!(parse(""), []);
+    }
+
+    #[test]
+    fn render_blanks_one_number_and_fills_the_rest() {
+        let text = "{{c1::crust}} over {{c2::mantle::layer}} over {{c1::core}}";
+        assert_eq!(numbers(text), BTreeSet::from([1, 2]));
+        assert_eq!(
+            render(text, 1),
+            (
+                "[...] over mantle over [...]".to_string(),
+                "crust, core".to_string()
+            )
+        );
+        assert_eq!(
+            render(text, 2),
+            (
+                "crust over [layer] over core".to_string(),
+                "mantle".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn item_ids_are_recognised() {
+        let id = item_id("card", 3);
+        assert_eq!(id, "card/c3");
+        assert!(is_item_id(&id));
+        assert!(!is_item_id("card"));
+        assert!(!is_item_id("card/reverse"));
+    }
+}
@@ -2,7 +2,33 @@ use html_builder::prelude::*;
 use std::fmt::Display;
 use std::option::Option;
 
-use crate::data::{self, Set, Subject};
+use crate::csv;
+use crate::data::{self, Direction, Folder, Set, Side, Subject};
+use crate::grading::{Grade, Segment};
+use crate::import;
+use crate::matching::{MatchTime, Round};
+use crate::media;
+use crate::paste::Pasted;
+use crate::quiz::Question;
+use crate::scheduler::{self, Algorithm, Leitner};
+use crate::session::{CramCard, StudySession};
+use crate::test_paper::TestPaper;
+
+/// A full page with the stylesheet, htmx and sound effects loaded.
+pub fn page(title: impl Display, body: Body) -> Html {
+    html("en")
+        .child(
+            head()
+                .template()
+                .style(include_str!("./output.css"))
+                .title(title)
+                .raw_text("<script src='/assets/htmx.min.js'></script>"),
+        )
+        .child(
+            body.class("p-8 grid place-items-center items-start gap-8 bg-neutral")
+                .script(include_str!("../script.js")),
+        )
+}
 
 pub fn text_input(
     id: impl Display,
@@ -22,22 +48,138 @@ pub fn text_input(
         .child(label(&id).class("absolute left-0 w-full h-fit transition-all duration-input text-left ml-3 cursor-text bottom-1/2 translate-y-1/2 peer-typing:text-accent-600 peer-typing:text-xs peer-typing:translate-y-[-1em] peer-typing:font-bold").text(&label_text))
 }
 
-pub fn flashcard_stack(cards: impl IntoIterator<Item = data::Card>) -> Div {
+/// A box for text that can run over several lines, such as a definition.
+pub fn text_area(
+    id: impl Display,
+    name: impl Display,
+    label_text: impl Display,
+    value: Option<&str>,
+) -> Div {
+    let area = textarea()
+        .id(&id)
+        .name(&name)
+        .class("input input-gray w-full min-h-[6ch] p-2 text-left")
+        .placeholder(&label_text);
+    div().class("w-full").child(if let Some(value) = value {
+        area.text(value)
+    } else {
+        area
+    })
+}
+
+/// The session's current card with rating buttons that answer it and swap in
+/// the next one, or a note that the session is over.
+pub fn study_card(session: &StudySession, card: Option<&data::Card>) -> Div {
     // tailwind include: btn-terrible btn-bad btn-ok btn-good btn-perfect
-    div()
-        .class("grid place-items-center gap-4")
+    let container = div().id("study").class("grid place-items-center gap-4");
+    let (Some(item), Some(card)) = (&session.current, card) else {
+        return container
+            .child(p(format!(
+                "session finished: {} cards studied (come back later)",
+                session.answered.len()
+            )))
+            .child(a("/").class("underline").text("home"));
+    };
+    let shown = scheduler::now_millis();
+    container
+        .child(p(format!(
+            "{} to go, {} learning, {} done",
+            session.queue.len(),
+            session.learning.len(),
+            session.answered.len()
+        )))
+        .child(flashcard(card.clone(), item.front))
         .child(
-            div()
-                .class("flashcard-stack")
-                .children(cards.into_iter().map(flashcard)),
+            button_with_icon("btn-flip", "flip", "flip")
+                .class("input-accent")
+                .hx_on(
+                    "click",
+                    "document.querySelectorAll('#study .card').forEach(card => card.classList.toggle('flashcard-hidden'))",
+                ),
         )
-        .child(button_with_icon("btn-flip", "flip", "flip").class("input-accent"))
         .child(horizontal_btn_group(data::Rating::all().map(|rating| {
-            button_with_icon(format!("btn-{rating}"), rating, "").title(rating)
+            let query = url::form_urlencoded::Serializer::new(item.to_query())
+                .append_pair("rating", &rating.to_string())
+                .append_pair("shown", &shown.to_string())
+                .finish();
+            button_with_icon(format!("btn-{rating}"), rating, "")
+                .title(rating)
+                .hx_post(format!("/session/{}/answer?{query}", session.id))
+                .hx_target("#study")
+                .hx_swap("outerHTML")
         })))
 }
 
-pub fn flashcard(card: data::Card) -> Article {
+/// The end of a cram, with the cards that took the most goes.
+pub fn cram_summary(session: &StudySession, weakest: &[(data::Card, &CramCard)]) -> Div {
+    let summary = div()
+        .id("study")
+        .class("grid place-items-center gap-4")
+        .child(h2("Cram finished"))
+        .child(p(format!("{} cards crammed", session.answered.len())));
+    let summary = if weakest.is_empty() {
+        summary.child(p("not a single miss (nice)"))
+    } else {
+        summary
+            .child(h3("Weakest cards"))
+            .child(
+                div()
+                    .class("grid gap-2 w-full")
+                    .children(weakest.iter().map(|(card, cram)| {
+                        div()
+                            .class("card grid grid-cols-[1fr,1fr,auto] gap-4")
+                            .child(p(card.side(cram.item.front).trim()))
+                            .child(p(card.side(cram.item.front.other()).trim()))
+                            .child(p(format!("missed {} of {}", cram.misses, cram.attempts)))
+                    })),
+            )
+    };
+    summary.child(a("/").class("underline").text("home"))
+}
+
+/// Sessions that were left part of the way through, to pick up again.
+pub fn session_list(sessions: &[&StudySession]) -> Section {
+    let section = section().class("grid gap-2 w-full");
+    if sessions.is_empty() {
+        return section;
+    }
+    section
+        .child(h2("Carry on studying"))
+        .children(sessions.iter().map(|session| {
+            a(format!("/session/{}", session.id))
+                .class("card flex justify-between")
+                .child(p(&session.title))
+                .child(p(format!(
+                    "{} to go",
+                    session.queue.len() + session.learning.len()
+                )))
+        }))
+}
+
+/// Links to study the set each way round, with the current direction highlighted.
+pub fn direction_menu(set_id: &str, current: Direction) -> Nav {
+    nav()
+        .class("flex gap-4")
+        .children(Direction::all().map(|direction| {
+            let link = a(format!("/sets/{set_id}?direction={direction}")).text(match direction {
+                Direction::Forward => "term first",
+                Direction::Reverse => "definition first",
+                Direction::Both => "both ways",
+            });
+            if direction == current {
+                link.class("font-bold")
+            } else {
+                link.class("underline")
+            }
+        }))
+}
+
+/// A card showing its `front` side, with the other side hidden until it is flipped.
+pub fn flashcard(card: data::Card, front: Side) -> Article {
+    let (front, back) = (
+        card.side(front).to_string(),
+        card.side(front.other()).to_string(),
+    );
     article()
             .class("grid place-items-center gap-4 text-center")
             .data("card-id", card.id)
@@ -49,9 +191,7 @@ pub fn flashcard(card: data::Card) -> Article {
                         .child(
                             div()
                                 .class("card-body")
-                                .child(
-                                    p(card.term)
-                                )
+                                .child(card_text(&front))
                         )
                 )
                 .child(
@@ -61,17 +201,1004 @@ pub fn flashcard(card: data::Card) -> Article {
                         .child(
                             div()
                                 .class("card-body")
-                                .child(
-                                    p(card.definition)
-                                )
+                                .child(card_text(&back))
+                        )
+                )
+            )
+}
+
+/// A side of a card, with any `![alt](source)` in it shown as an image and any
+/// `[sound:name]` as an audio player.
+pub fn card_text(text: &str) -> Div {
+    let mut container = div().class("grid place-items-center gap-2");
+    let mut rest = text;
+    loop {
+        let image = media::image(rest);
+        let sound = media::sound(rest);
+        let (before, element, after) = match (image, sound) {
+            (Some((before, alt, source, after)), sound)
+                if sound.is_none_or(|(sound_before, ..)| before.len() < sound_before.len()) =>
+            {
+                (
+                    before,
+                    div().child(img(source, alt).class("max-h-[30ch] w-auto")),
+                    after,
+                )
+            }
+            (_, Some((before, name, after))) => (
+                before,
+                // media names are made safe for URLs (and so for HTML) by `file_name`
+                div().raw_text(format!(
+                    "<audio controls src=\"/media/{}\"></audio>",
+                    media::file_name(name)
+                )),
+                after,
+            ),
+            _ => break,
+        };
+        if !before.trim().is_empty() {
+            container = container.child(p(before.trim()));
+        }
+        container = container.child(element);
+        rest = after;
+    }
+    if !rest.trim().is_empty() {
+        container = container.child(p(rest.trim()));
+    }
+    container
+}
+
+pub fn set_details_form(set: &Set) -> Form {
+    form()
+        .id("set-details")
+        .class("card w-full grid gap-4")
+        .hx_post(format!("/edit-set/{}", set.id))
+        .hx_swap("outerHTML")
+        .child(text_input(
+            "title",
+            "title",
+            "title",
+            InputType::Text,
+            true,
+            Some(set.title.clone()),
+        ))
+        .child(text_input(
+            "description",
+            "description",
+            "description",
+            InputType::Text,
+            false,
+            Some(set.description.clone()),
+        ))
+        .child(button_with_icon("save-details", "publish", "save").class("input-accent"))
+}
+
+/// Buttons to choose how the set is scheduled, with the current choice highlighted.
+pub fn algorithm_picker(set: &Set) -> Div {
+    let choices = std::iter::once(("default".to_string(), set.algorithm.is_none())).chain(
+        Algorithm::all()
+            .into_iter()
+            .map(|algorithm| (algorithm.to_string(), set.algorithm == Some(algorithm))),
+    );
+    algorithm_buttons(
+        "scheduling",
+        &format!("/edit-set/{}/algorithm", set.id),
+        choices,
+    )
+}
+
+/// Buttons to choose the algorithm for sets that don't choose their own.
+pub fn default_algorithm_picker(algorithm: Algorithm) -> Div {
+    let choices = Algorithm::all()
+        .into_iter()
+        .map(|choice| (choice.to_string(), choice == algorithm));
+    algorithm_buttons("default scheduling", "/settings/algorithm", choices)
+}
+
+/// A row of `(name, chosen)` buttons that each post to `{url}/{name}` and are
+/// swapped for the row the response gives back.
+fn algorithm_buttons(title: &str, url: &str, choices: impl Iterator<Item = (String, bool)>) -> Div {
+    div()
+        .id("algorithm-picker")
+        .class("card w-full grid gap-2")
+        .child(p(title))
+        .child(horizontal_btn_group(choices.map(|(name, chosen)| {
+            button(format!("algorithm-{name}"))
+                .class(if chosen {
+                    "btn input-accent"
+                } else {
+                    "btn input-gray"
+                })
+                .hx_post(format!("{url}/{name}"))
+                .hx_target("#algorithm-picker")
+                .hx_swap("outerHTML")
+                .child(p(name))
+        })))
+}
+
+/// A form to set the days between reviews for each Leitner box, for every set
+/// scheduled with Leitner boxes.
+pub fn leitner_intervals_form(leitner: &Leitner) -> Form {
+    form()
+        .id("leitner-intervals")
+        .class("card w-full grid gap-4")
+        .hx_post("/settings/leitner")
+        .hx_swap("outerHTML")
+        .child(p("days between reviews for each leitner box"))
+        .child(text_input(
+            "intervals",
+            "intervals",
+            "intervals, such as 1, 3, 7",
+            InputType::Text,
+            true,
+            Some(leitner.to_string()),
+        ))
+        .child(button_with_icon("save-intervals", "publish", "save").class("input-accent"))
+}
+
+/// How many cards are in each Leitner box, as a row of bars.
+pub fn leitner_boxes(boxes: &[usize], unboxed: usize) -> Section {
+    // tailwind include: h-0 h-4 h-8 h-12 h-16 h-20 h-24 h-28 h-32
+    let most = boxes
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or_default()
+        .max(unboxed)
+        .max(1);
+    let column = |label: String, count: usize| {
+        div()
+            .class("grid gap-1 place-items-center")
+            .child(
+                div().class("w-12 h-32 flex items-end").child(
+                    div()
+                        .class("w-full bg-accent-600 rounded-t")
+                        .class(format!("h-{}", count * 8 / most * 4)),
+                ),
+            )
+            .child(p(count.to_string()).class("font-bold"))
+            .child(p(label).class("text-sm"))
+    };
+    section()
+        .class("card grid grid-flow-col gap-4 w-fit")
+        .child(column("new".to_string(), unboxed))
+        .children(
+            boxes
+                .iter()
+                .enumerate()
+                .map(|(index, count)| column(format!("box {}", index + 1), *count)),
+        )
+}
+
+/// An inline form for one card, with buttons to save, move and delete it.
+pub fn card_editor(set_id: &str, card: &data::Card) -> Form {
+    let url = format!("/edit-set/{set_id}/cards/{}", card.id);
+    let list_action = |id: String, action: &str| {
+        button(format!("{action}-{id}"))
+            .class("btn")
+            .hx_post(format!("{url}/{action}"))
+            .hx_target("#card-editor-list")
+            .hx_swap("outerHTML")
+    };
+    form()
+        .id(format!("card-{}", card.id))
+        .class("card w-full grid grid-cols-[1fr,1fr,auto] gap-4 items-center")
+        .hx_post(&url)
+        .hx_swap("outerHTML")
+        .child(text_input(
+            format!("term-{}", card.id),
+            "term",
+            "term",
+            InputType::Text,
+            true,
+            Some(card.term.trim().to_string()),
+        ))
+        .child(text_area(
+            format!("definition-{}", card.id),
+            "definition",
+            "definition",
+            Some(card.definition.trim()),
+        ))
+        .child(horizontal_btn_group([
+            button_with_icon(format!("save-{}", card.id), "publish", "save"),
+            list_action(card.id.clone(), "up").child(p("↑")),
+            list_action(card.id.clone(), "down").child(p("↓")),
+            list_action(card.id.clone(), "delete")
+                .child(img("/assets/delete.svg", "delete").size(24, 24)),
+        ]))
+}
+
+pub fn card_editor_list(set: &Set) -> Div {
+    div()
+        .id("card-editor-list")
+        .class("grid w-full gap-4")
+        .children(set.cards.iter().map(|card| card_editor(&set.id, card)))
+        .child(
+            form()
+                .id("new-card")
+                .class("card w-full grid grid-cols-[1fr,1fr,auto] gap-4 items-center")
+                .hx_post(format!("/edit-set/{}/cards", set.id))
+                .hx_target("#card-editor-list")
+                .hx_swap("outerHTML")
+                .child(text_input(
+                    "new-term",
+                    "term",
+                    "term",
+                    InputType::Text,
+                    true,
+                    None,
+                ))
+                .child(text_area(
+                    "new-definition",
+                    "definition",
+                    "definition",
+                    None,
+                ))
+                .child(button_with_icon("add-card", "add", "add card").class("input-accent")),
+        )
+        .child(p("tip: write {{c1::a word}} in a term to make a cloze card, which hides each numbered cloze in turn").class("text-sm opacity-75"))
+}
+
+/// Radio buttons for choosing a subject, styled like the subject menu.
+pub fn subject_picker(subjects: &[Subject]) -> Div {
+    div()
+        .class("grid grid-flow-col gap-1 w-fit")
+        .children(subjects.iter().map(|Subject { id, name, color }| {
+            label(format!("pick-subject-{id}"))
+                .class(format!(
+                    "btn input-{color} first:rounded-l-lg last:rounded-r-lg rounded-none has-[:checked]:ring-2"
+                ))
+                .child(
+                    input()
+                        .r#type(InputType::Radio)
+                        .class("sr-only")
+                        .id(format!("pick-subject-{id}"))
+                        .name("subject")
+                        .value(id.clone()),
+                )
+                .child(img(format!("/assets/{id}.svg"), name).size(24, 24))
+                .child(p(name))
+        }))
+}
+
+/// Blank term and definition inputs numbered from `start`, followed by a button
+/// that replaces itself with another row.
+pub fn new_card_rows(start: usize, count: usize) -> Div {
+    div()
+        .class("grid w-full gap-4")
+        .children((start..start + count).map(|index| {
+            div()
+                .class("grid grid-cols-2 gap-4 w-full")
+                .child(text_input(
+                    format!("term-{index}"),
+                    format!("term-{index}"),
+                    "term",
+                    InputType::Text,
+                    false,
+                    None,
+                ))
+                .child(text_area(
+                    format!("definition-{index}"),
+                    format!("definition-{index}"),
+                    "definition",
+                    None,
+                ))
+        }))
+        .child(
+            button_with_icon(format!("add-card-row-{}", start + count), "add", "add card")
+                .hx_get(format!("/view/card-row?index={}", start + count))
+                .hx_swap("outerHTML"),
+        )
+}
+
+pub fn create_set_form(subjects: &[Subject]) -> Form {
+    form()
+        .id("create-set")
+        .class("card w-full grid gap-4")
+        .hx_post("/create-set")
+        .hx_target("#create-set-error")
+        .hx_swap("innerHTML")
+        .child(subject_picker(subjects))
+        .child(text_input(
+            "title",
+            "title",
+            "title",
+            InputType::Text,
+            true,
+            None,
+        ))
+        .child(text_input(
+            "description",
+            "description",
+            "description",
+            InputType::Text,
+            false,
+            None,
+        ))
+        .child(h3("cards"))
+        .child(new_card_rows(0, 3))
+        .child(div().id("create-set-error").class("text-red-500"))
+        .child(button_with_icon("create-set-submit", "create", "create").class("input-accent"))
+}
+
+/// The "create new" button in the corner of the page. Remixing is offered when
+/// there is a set to remix.
+pub fn create_menu(set: Option<&Set>) -> Div {
+    let create = button_with_icon("fab-create-set", "set", "create set")
+        .hx_on("click", "location.href='/create-set'");
+    let remix = set.map(|set| {
+        button_with_icon("fab-create-remix", "star", "create remix")
+            .hx_post(format!("/sets/{}/remix", set.id))
+    });
+    let paste = button_with_icon("fab-paste-cards", "edit", "paste cards")
+        .hx_on("click", "location.href='/import/text'");
+    let spreadsheet = button_with_icon("fab-import-csv", "add", "import a spreadsheet")
+        .hx_on("click", "location.href='/import/csv'");
+    let notes = button_with_icon("fab-import-markdown", "add", "import notes")
+        .hx_on("click", "location.href='/import/markdown'");
+    let anki = button_with_icon("fab-import-anki", "add", "import from Anki")
+        .hx_on("click", "location.href='/import/anki'");
+    fab_dropdown(
+        "create-new",
+        "create",
+        [create]
+            .into_iter()
+            .chain(remix)
+            .chain([paste, spreadsheet, notes, anki]),
+    )
+}
+
+/// The spreadsheet upload form, with a choice of delimiter and of the column each
+/// card field comes from, and a button to preview the cards before importing.
+pub fn csv_form(subjects: &[Subject]) -> Form {
+    let options = div()
+        .class("grid gap-4")
+        .child(text_input(
+            "csv-title",
+            "title",
+            "title (the file's name if left empty)",
+            InputType::Text,
+            false,
+            None,
+        ))
+        .child(p(
+            "columns are split at (worked out from the file if none is picked)",
+        ))
+        .child(radio_group(
+            "delimiter",
+            &[
+                ("comma", "comma"),
+                ("tab", "tab"),
+                ("semicolon", "semicolon"),
+            ],
+        ))
+        .child(p(
+            "the column each field is in, by header or number (found by header if left empty)",
+        ))
+        .children(csv::Field::all().map(|field| {
+            text_input(
+                format!("csv-{field}-column"),
+                format!("{field}-column"),
+                format!("{field} column"),
+                InputType::Text,
+                false,
+                None,
+            )
+        }))
+        .child(
+            button_with_icon("csv-preview", "study", "preview")
+                .class("w-fit")
+                .hx_on(
+                    "click",
+                    "event.preventDefault();uploadFile(this.form,'/view/csv-preview')",
+                ),
+        );
+    upload_form("csv", "/import/csv", subjects, Some(options))
+}
+
+/// The columns a spreadsheet's fields would be read from, the first `count` cards
+/// it would make and the rows that couldn't be read.
+pub fn csv_preview(import: &csv::Import, count: usize) -> Div {
+    let column = |index: usize| {
+        import
+            .header
+            .as_ref()
+            .and_then(|header| header.get(index))
+            .map_or_else(
+                || format!("column {}", index + 1),
+                |name| format!("column {} ({name})", index + 1),
+            )
+    };
+    div()
+        .class("grid gap-2")
+        .children(
+            import
+                .columns
+                .iter()
+                .map(|(field, index)| p(format!("{field}: {}", column(*index)))),
+        )
+        .child(p(format!(
+            "{} cards, {} rows with problems",
+            import.cards.len(),
+            import.errors.len()
+        )))
+        .children(import.cards.iter().take(count).map(|card| {
+            div()
+                .class("card grid grid-cols-2 gap-4 w-full text-left")
+                .child(card_text(&card.term))
+                .child(card_text(&card.definition))
+        }))
+        .children(
+            import
+                .errors
+                .iter()
+                .map(|error| p(error).class("text-red-500")),
+        )
+}
+
+/// The set a spreadsheet was imported into, if any cards could be, and the rows
+/// that were left out.
+pub fn csv_report(set: Option<&Set>, errors: &[String]) -> Div {
+    div()
+        .class("grid gap-2")
+        .child(set.map_or_else(
+            || p("none of the rows could be imported"),
+            |set| {
+                p(format!("imported {} cards into ", set.cards.len())).child(
+                    a(format!("/sets/{}", set.id))
+                        .class("underline")
+                        .text(&set.title),
+                )
+            },
+        ))
+        .children(errors.iter().map(|error| p(error).class("text-red-500")))
+}
+
+/// Radio buttons for the separator called `name`, the last of which uses the
+/// text box next to them.
+fn separator_picker(name: &str, options: &[(&str, &str)]) -> Div {
+    div()
+        .class("flex gap-4 items-center")
+        .child(radio_group(name, options))
+        .child(text_input(
+            format!("{name}-custom"),
+            format!("{name}-custom"),
+            "custom (\\t for tab, \\n for new line)",
+            InputType::Text,
+            false,
+            None,
+        ))
+}
+
+/// Radio buttons called `name`, each a value and its label.
+fn radio_group(name: &str, options: &[(&str, &str)]) -> Div {
+    div()
+        .class("grid grid-flow-col gap-1 w-fit")
+        .children(options.iter().map(|(value, text)| {
+            label(format!("{name}-{value}"))
+                .class("btn input-gray first:rounded-l-lg last:rounded-r-lg rounded-none has-[:checked]:ring-2")
+                .child(
+                    input()
+                        .r#type(InputType::Radio)
+                        .class("sr-only")
+                        .id(format!("{name}-{value}"))
+                        .name(name)
+                        .value(*value),
+                )
+                .child(p(text))
+        }))
+}
+
+/// Pasted text with a choice of separators, previewed below as it changes.
+pub fn paste_form(subjects: &[Subject]) -> Form {
+    form()
+        .id("paste")
+        .class("card w-full grid gap-4")
+        .hx_post("/view/paste-preview")
+        .hx_target("#paste-preview")
+        .hx_swap("innerHTML")
+        .data("hx-trigger", "input delay:300ms, change")
+        .child(subject_picker(subjects))
+        .child(text_input(
+            "title",
+            "title",
+            "title",
+            InputType::Text,
+            true,
+            None,
+        ))
+        .child(p(
+            "between each term and its definition (tab if none is picked)",
+        ))
+        .child(separator_picker(
+            "term-separator",
+            &[("tab", "tab"), ("comma", "comma"), ("custom", "custom")],
+        ))
+        .child(p("between cards (new line if none is picked)"))
+        .child(separator_picker(
+            "card-separator",
+            &[
+                ("newline", "new line"),
+                ("semicolon", "semicolon"),
+                ("custom", "custom"),
+            ],
+        ))
+        .child(
+            textarea()
+                .id("paste-text")
+                .name("text")
+                .class("input input-gray w-full min-h-[20ch] p-2 text-left")
+                .placeholder("paste your cards here"),
+        )
+        .child(div().id("paste-error").class("text-red-500"))
+        .child(
+            button_with_icon("paste-submit", "create", "import")
+                .class("input-accent")
+                .hx_post("/import/text")
+                .hx_target("#paste-error")
+                .hx_swap("innerHTML"),
+        )
+        .child(section().id("paste-preview").class("grid gap-4"))
+}
+
+/// The cards pasted text would make, and the bits of it that aren't cards.
+pub fn paste_preview(pasted: &Pasted) -> Div {
+    div()
+        .class("grid gap-4")
+        .child(p(format!("{} cards", pasted.cards.len())))
+        .children(
+            pasted
+                .problems
+                .iter()
+                .map(|problem| p(problem).class("text-red-500")),
+        )
+        .children(
+            pasted
+                .cards
+                .iter()
+                .map(|card| flashcard(card.clone(), Side::Term)),
+        )
+}
+
+/// A subject to import into and a file, which is uploaded to `url` with the
+/// form's other fields (including any `options`) in the query string. The report
+/// the upload answers with is shown at the bottom of the form.
+pub fn upload_form(name: &str, url: &str, subjects: &[Subject], options: Option<Div>) -> Form {
+    form()
+        .id(format!("{name}-upload"))
+        .class("card w-full grid gap-4")
+        .hx_on(
+            "submit",
+            format!("event.preventDefault();uploadFile(this,'{url}')"),
+        )
+        .child(subject_picker(subjects))
+        .child(input().r#type(InputType::File).name("file"))
+        .children(options)
+        .child(
+            button_with_icon(format!("{name}-upload-submit"), "add", "import")
+                .class("input-accent"),
+        )
+        .child(div().class("upload-report"))
+}
+
+/// What an import made, with links to the new sets, and what it left out.
+pub fn import_report(report: &import::Report) -> Div {
+    div()
+        .class("grid gap-2")
+        .child(p(format!(
+            "imported {} sets into {} folders{}",
+            report.sets.len(),
+            report.folders.len(),
+            if report.media > 0 {
+                format!(", with {} media files", report.media)
+            } else {
+                String::new()
+            }
+        )))
+        .children(report.sets.iter().map(|(id, title, cards)| {
+            a(format!("/sets/{id}"))
+                .class("underline")
+                .text(format!("{title} ({cards} cards)"))
+        }))
+        .children(
+            report.skipped.iter().map(|(reason, count)| {
+                p(format!("skipped {count} {reason}")).class("text-red-500")
+            }),
+        )
+}
+
+/// A card's prompt with a box to type the `answer` side into.
+pub fn write_prompt(set_id: &str, card: Option<&data::Card>, answer: Side) -> Div {
+    let container = div()
+        .id("write")
+        .class("grid place-items-center gap-4 w-full");
+    let Some(card) = card else {
+        return container.child(p("nothing left to write (come back later)"));
+    };
+    container
+        .child(
+            div()
+                .class("card min-w-[60ch] min-h-[20ch] grid place-items-center")
+                .child(p(card.side(answer.other()).trim())),
+        )
+        .child(
+            form()
+                .class("grid gap-4 w-full")
+                .hx_post(format!(
+                    "/sets/{set_id}/cards/{}/write?answer={answer}&shown={}",
+                    card.id,
+                    scheduler::now_millis()
+                ))
+                .hx_target("#write")
+                .hx_swap("outerHTML")
+                .child(text_input(
+                    "answer",
+                    "answer",
+                    format!("type the {answer}"),
+                    InputType::Text,
+                    true,
+                    None,
+                ))
+                .child(button_with_icon("check-answer", "ok", "check").class("input-accent")),
+        )
+}
+
+/// How a typed answer was marked, with the differences from the real answer.
+pub fn write_feedback(set_id: &str, card: &data::Card, grade: &Grade, answer: Side) -> Div {
+    div()
+        .id("write")
+        .class("grid place-items-center gap-4 w-full")
+        .child(
+            div()
+                .class("card min-w-[60ch] grid gap-4")
+                .child(p(card.side(answer.other()).trim()))
+                .child(
+                    div()
+                        .class("flex flex-wrap gap-1")
+                        .children(grade.diff.iter().map(|segment| match segment {
+                            Segment::Same(text) => span(text),
+                            Segment::Missing(text) => span(text).class("text-green-500 underline"),
+                            Segment::Extra(text) => span(text).class("text-red-500 line-through"),
+                        })),
+                )
+                .children(
+                    (!grade.missing_keywords.is_empty())
+                        .then(|| p(format!("missing: {}", grade.missing_keywords.join(", ")))),
+                )
+                .child(
+                    div()
+                        .class("flex gap-2 items-center")
+                        .child(
+                            img(format!("/assets/{}.svg", grade.rating), grade.rating).size(24, 24),
                         )
+                        .child(p(format!("{} ({:.0}%)", grade.rating, grade.score * 100.0))),
+                ),
+        )
+        .child(
+            button_with_icon("next-write", "flip", "next")
+                .class("input-accent")
+                .hx_get(format!("/view/write?set={set_id}&answer={answer}"))
+                .hx_target("#write")
+                .hx_swap("outerHTML"),
+        )
+}
+
+/// A multiple-choice question; picking a choice marks it and swaps in the feedback.
+pub fn quiz_question(set_id: &str, question: Option<&Question>, answer: Side, seed: u64) -> Div {
+    let container = div()
+        .id("quiz")
+        .class("grid place-items-center gap-4 w-full");
+    let Some(question) = question else {
+        return container.child(p("nothing left to quiz (come back later)"));
+    };
+    let shown = scheduler::now_millis();
+    container
+        .child(
+            div()
+                .class("card min-w-[60ch] min-h-[20ch] grid place-items-center")
+                .child(p(&question.prompt)),
+        )
+        .child(vertical_btn_group(question.choices.iter().enumerate().map(
+            |(index, choice)| {
+                let chosen = url::form_urlencoded::byte_serialize(choice.as_bytes()).collect::<String>();
+                button(format!("choice-{index}"))
+                    .class("btn input-gray")
+                    .child(p(choice))
+                    .hx_post(format!(
+                        "/sets/{set_id}/cards/{}/quiz?choice={chosen}&answer={answer}&seed={seed}&shown={shown}",
+                        question.card.id
+                    ))
+                    .hx_target("#quiz")
+                    .hx_swap("outerHTML")
+            },
+        )))
+}
+
+/// The question again with the right choice (and the learner's, if it was wrong) marked.
+pub fn quiz_feedback(
+    set_id: &str,
+    question: &Question,
+    choice: &str,
+    answer: Side,
+    seed: u64,
+) -> Div {
+    let correct = choice == question.choices[question.answer];
+    div()
+        .id("quiz")
+        .class("grid place-items-center gap-4 w-full")
+        .child(
+            div()
+                .class("card min-w-[60ch] min-h-[20ch] grid place-items-center")
+                .child(p(&question.prompt)),
+        )
+        .child(vertical_btn_group(question.choices.iter().enumerate().map(
+            |(index, text)| {
+                let choice_button = button(format!("choice-{index}"))
+                    .class("btn")
+                    .child(p(text));
+                if index == question.answer {
+                    choice_button.class("btn-good")
+                } else if text == choice {
+                    choice_button.class("btn-terrible line-through")
+                } else {
+                    choice_button.class("input-gray")
+                }
+            },
+        )))
+        .child(p(if correct { "correct!" } else { "not quite" }))
+        .child(
+            button_with_icon("next-question", "flip", "next")
+                .class("input-accent")
+                .hx_get(format!(
+                    "/view/quiz?set={set_id}&answer={answer}&seed={seed}"
+                ))
+                .hx_target("#quiz")
+                .hx_swap("outerHTML"),
+        )
+}
+
+/// The tiles of a matching round with a running clock. Each click posts the tile
+/// and swaps in the round as it stands afterwards.
+pub fn match_round(round: &Round, best: &[MatchTime]) -> Div {
+    div()
+        .id("match")
+        .class("grid place-items-center gap-4 w-full")
+        .child(
+            p("0.0s")
+                .class("text-2xl font-bold")
+                .data("started", round.started),
+        )
+        .child(
+            div()
+                .class("grid grid-cols-3 md:grid-cols-4 gap-4 w-full")
+                .children(round.tiles.iter().enumerate().map(|(index, tile)| {
+                    let tile_button = button(format!("tile-{index}"))
+                        .class("card min-h-[10ch] grid place-items-center transition-opacity")
+                        .child(p(&tile.text));
+                    if tile.matched {
+                        tile_button.class("opacity-0 pointer-events-none")
+                    } else {
+                        let tile_button = if round.selected == Some(index) {
+                            tile_button.class("ring-4 ring-accent-600")
+                        } else if round
+                            .wrong
+                            .is_some_and(|(first, second)| index == first || index == second)
+                        {
+                            tile_button.class("ring-4 ring-red-500")
+                        } else {
+                            tile_button
+                        };
+                        tile_button
+                            .hx_post(format!("/match/{}/{index}", round.id))
+                            .hx_target("#match")
+                            .hx_swap("outerHTML")
+                    }
+                })),
+        )
+        .child(match_board(best))
+}
+
+/// The round's time once every pair is found, with the personal-best board.
+pub fn match_results(set_id: &str, time: &MatchTime, best: &[MatchTime]) -> Div {
+    let personal_best = best.first() == Some(time);
+    div()
+        .id("match")
+        .class("grid place-items-center gap-4 w-full")
+        .child(h2(format!("{:.1}s", seconds(time.millis))))
+        .children(personal_best.then(|| p("new personal best!").class("sound-yes")))
+        .child(p(format!("{} mistakes", time.mistakes)))
+        .child(
+            a(format!("/sets/{set_id}/match"))
+                .class("btn input-accent")
+                .text("play again"),
+        )
+        .child(match_board(best))
+}
+
+pub fn match_board(best: &[MatchTime]) -> Section {
+    let board = section()
+        .class("card grid gap-2")
+        .child(h3("Personal bests"));
+    if best.is_empty() {
+        return board.child(p("no times yet"));
+    }
+    board.children(best.iter().enumerate().map(|(place, time)| {
+        p(format!(
+            "{}. {:.1}s ({} mistakes)",
+            place + 1,
+            seconds(time.millis),
+            time.mistakes
+        ))
+    }))
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn seconds(millis: i64) -> f64 {
+    millis as f64 / 1000.0
+}
+
+/// `A`, `B`, `C`... for labelling choices, falling back to numbers past `Z`.
+fn letter(index: usize) -> String {
+    ('A'..='Z')
+        .nth(index)
+        .map_or_else(|| (index + 1).to_string(), String::from)
+}
+
+fn test_section(title: &str, instructions: &str) -> Section {
+    section()
+        .class("grid gap-4 break-inside-avoid")
+        .child(h2(title))
+        .child(p(instructions).class("italic"))
+}
+
+/// A test laid out for printing, with its questions numbered across the sections.
+pub fn test_paper(paper: &TestPaper) -> Article {
+    let set_id = &paper.set.id;
+    let key = format!(
+        "/sets/{set_id}/test/answers?count={}&seed={}",
+        paper.count, paper.seed
+    );
+    let mut number = 0;
+    let mut next = || {
+        number += 1;
+        number
+    };
+    let written = paper
+        .written
+        .iter()
+        .map(|card| {
+            div()
+                .class("grid gap-2")
+                .child(p(format!("{}. {}", next(), card.term.trim())))
+                .child(div().class("border-b border-black h-8"))
+        })
+        .collect::<Vec<_>>();
+    let multiple_choice =
+        paper
+            .multiple_choice
+            .iter()
+            .map(|question| {
+                div()
+                    .class("grid gap-1")
+                    .child(p(format!("{}. {}", next(), question.prompt)))
+                    .children(question.choices.iter().enumerate().map(|(index, choice)| {
+                        p(format!("{}) {choice}", letter(index))).class("ml-6")
+                    }))
+            })
+            .collect::<Vec<_>>();
+    let true_false = paper
+        .true_false
+        .iter()
+        .map(|item| {
+            p(format!(
+                "{}. {} - {}    true / false",
+                next(),
+                item.card.term.trim(),
+                item.shown
+            ))
+        })
+        .collect::<Vec<_>>();
+    let matching = &paper.matching;
+    let matching_terms = matching
+        .terms
+        .iter()
+        .map(|card| p(format!("{}. {} ____", next(), card.term.trim())))
+        .collect::<Vec<_>>();
+    let sections =
+        [
+            (!written.is_empty()).then(|| {
+                test_section("Written", "Write the definition of each term.").children(written)
+            }),
+            (!multiple_choice.is_empty()).then(|| {
+                test_section("Multiple choice", "Circle the right definition.")
+                    .children(multiple_choice)
+            }),
+            (!true_false.is_empty()).then(|| {
+                test_section(
+                    "True or false",
+                    "Circle whether each term is paired with its definition.",
                 )
+                .children(true_false)
+            }),
+            (!matching_terms.is_empty()).then(|| {
+                test_section("Matching", "Write the letter of each term's definition.").child(
+                    div()
+                        .class("grid grid-cols-2 gap-8")
+                        .child(div().class("grid gap-2").children(matching_terms))
+                        .child(
+                            div().class("grid gap-2").children(
+                                matching.definitions.iter().enumerate().map(
+                                    |(index, definition)| {
+                                        p(format!("{}) {definition}", letter(index)))
+                                    },
+                                ),
+                            ),
+                        ),
+                )
+            }),
+        ];
+    article()
+        .class("bg-white text-black p-8 grid gap-8 w-full max-w-[80ch] print:p-0")
+        .child(h1(format!("{} test", paper.set.title)))
+        .child(p("name: ______________________    date: __________"))
+        .children(sections.into_iter().flatten())
+        .child(
+            nav()
+                .class("flex gap-4 print:hidden")
+                .child(
+                    button("print-test")
+                        .class("btn input-accent")
+                        .hx_on("click", "window.print()")
+                        .child(p("print")),
+                )
+                .child(a(key).class("underline").text("answer key"))
+                .child(
+                    a(format!("/sets/{set_id}/test?count={}", paper.count))
+                        .class("underline")
+                        .text("another test"),
+                ),
+        )
+}
+
+/// The answers to a test, numbered the same way as the test itself.
+pub fn test_answer_key(paper: &TestPaper) -> Article {
+    let answers = paper
+        .written
+        .iter()
+        .map(|card| card.definition.trim().to_string())
+        .chain(paper.multiple_choice.iter().map(|question| {
+            format!(
+                "{}) {}",
+                letter(question.answer),
+                question.choices[question.answer]
             )
+        }))
+        .chain(
+            paper
+                .true_false
+                .iter()
+                .map(|item| if item.is_true { "true" } else { "false" }.to_string()),
+        )
+        .chain(paper.matching.answers.iter().map(|answer| letter(*answer)))
+        .enumerate()
+        .map(|(index, answer)| p(format!("{}. {answer}", index + 1)))
+        .collect::<Vec<_>>();
+    article()
+        .class("bg-white text-black p-8 grid gap-2 w-full max-w-[80ch] print:p-0")
+        .child(h1(format!("{} test: answers", paper.set.title)))
+        .child(p(format!("seed {}", paper.seed)).class("text-sm"))
+        .children(answers)
+        .child(
+            button("print-answers")
+                .class("btn input-accent print:hidden")
+                .hx_on("click", "window.print()")
+                .child(p("print")),
+        )
 }
 
 pub fn fab(id: impl Display, logo: impl Display) -> Button {
     button(id)
-        .child(img(format!("assets/{logo}.svg"), "").class("w-full h-full"))
+        .child(img(format!("/assets/{logo}.svg"), "").class("w-full h-full"))
         .class("input input-accent w-[7.5ch] h-[7.5ch] rounded-full")
 }
 
@@ -131,46 +1258,146 @@ pub fn loading_animation() -> Div {
         .child(h2("loading..."))
 }
 
-pub fn set_list(sets: &[Set]) -> Section {
+/// Links from the top of the subject down to the current folder.
+pub fn breadcrumbs(subject: &str, folders: &[Folder]) -> Nav {
+    let link = |text: &str, query: String| {
+        a(format!("/?{query}"))
+            .class("underline")
+            .hx_get(format!("/view/sets?{query}"))
+            .hx_push_url(format!("?{query}"))
+            .hx_target("#setlist")
+            .hx_swap("outerHTML swap:200ms")
+            .text(text)
+    };
+    folders.iter().fold(
+        nav()
+            .class("col-span-full flex gap-2")
+            .child(link(subject, format!("subject={subject}"))),
+        |nav, folder| {
+            nav.child(span("/")).child(link(
+                &folder.title,
+                format!("subject={subject}&folder={}", folder.id),
+            ))
+        },
+    )
+}
+
+pub fn folder_card(folder: &Folder) -> Article {
+    let query = format!("subject={}&folder={}", folder.subject, folder.id);
+    article()
+        .class("card w-full bg-neutral-900")
+        .child(
+            div()
+                .class("flex gap-2 items-center")
+                .child(img("/assets/folder.svg", "folder").size(24, 24))
+                .child(h3(&folder.title)),
+        )
+        .child(
+            button(format!("folder-{}", folder.id))
+                .class("btn input-gray w-full sound-open")
+                .hx_get(format!("/view/sets?{query}"))
+                .hx_push_url(format!("?{query}"))
+                .hx_target("#setlist")
+                .hx_swap("outerHTML swap:200ms")
+                .child(p("open")),
+        )
+}
+
+pub fn set_list(
+    subject: Option<&str>,
+    breadcrumbs: &[Folder],
+    folders: &[Folder],
+    sets: &[Set],
+) -> Section {
     let section = section()
         .id("setlist")
         .class("grid grid-cols-3 w-full gap-4 fade-out");
-    if sets.is_empty() {
+    let section = match subject {
+        Some(subject) => section.child(self::breadcrumbs(subject, breadcrumbs)),
+        None => section,
+    };
+    // studies every ticked set together, or everything here if none are ticked
+    let section = match subject {
+        Some(subject) => {
+            let folder = breadcrumbs
+                .last()
+                .map(|folder| format!("&folder={}", folder.id))
+                .unwrap_or_default();
+            section.child(
+                button_with_icon("study-together", "study", "study together")
+                    .class("col-span-full input-accent w-fit")
+                    .hx_on(
+                        "click",
+                        format!(
+                            "const sets = [...document.querySelectorAll('input[name=sets]:checked')].map(input => encodeURIComponent(input.value)); \
+                            location.href = sets.length ? `/study?sets=${{sets.join(',')}}` : '/study?subject={subject}{folder}'"
+                        ),
+                    ),
+            )
+            .child(
+                a(format!("/subjects/{subject}/export.apkg"))
+                    .class("underline w-fit")
+                    .text("export to Anki"),
+            )
+        }
+        None => section,
+    };
+    if sets.is_empty() && folders.is_empty() {
         section.child(
             p("i couldn't find any sets (where it's at?)").class("col-span-full text-center"),
         )
     } else {
-        section.children(sets.iter().map(|set| {
-            article()
-                .class(format!("card w-full bg-{}-950", set.subject.color))
-                .child(h3(&set.title))
-                .child(p(&set.description))
-                .child(
-                    div()
-                        .class("w-full flex justify-between")
-                        .child(p("69 cards"))
-                        .child(p(&set.subject.name).class(format!(
-                            "rounded-full border border-black dark:border-white px-2 bg-{}-800",
-                            set.subject.color
-                        ))),
-                )
-                .child(
-                    div()
-                        .class("grid grid-flow-col w-full gap-2")
-                        .child(
-                            a(format!("/sets/{}", set.id))
-                                .class(format!("btn input-{} w-full sound-yes", set.subject.color))
-                                .child(img("/assets/study.svg", "study").size(24, 24))
-                                .child(p("study")),
-                        )
-                        .child(
-                            a(format!("/edit-set/{}", set.id))
-                                .class(format!("btn input-{} w-full sound-yes", set.subject.color))
-                                .child(img("/assets/edit.svg", "edit").size(24, 24))
-                                .child(p("edit")),
-                        ),
-                )
-        }))
+        section
+            .children(folders.iter().map(folder_card))
+            .children(sets.iter().map(|set| {
+                article()
+                    .class(format!("card w-full bg-{}-950", set.subject.color))
+                    .child(h3(&set.title))
+                    .child(p(&set.description))
+                    .child(
+                        div()
+                            .class("w-full flex justify-between")
+                            .child(p("69 cards"))
+                            .child(p(&set.subject.name).class(format!(
+                                "rounded-full border border-black dark:border-white px-2 bg-{}-800",
+                                set.subject.color
+                            ))),
+                    )
+                    .child(
+                        label(format!("select-{}", set.id))
+                            .class("flex gap-2 items-center")
+                            .child(
+                                input()
+                                    .r#type(InputType::Checkbox)
+                                    .id(format!("select-{}", set.id))
+                                    .name("sets")
+                                    .value(set.id.clone()),
+                            )
+                            .child(p("study with others")),
+                    )
+                    .child(
+                        div()
+                            .class("grid grid-flow-col w-full gap-2")
+                            .child(
+                                a(format!("/sets/{}", set.id))
+                                    .class(format!(
+                                        "btn input-{} w-full sound-yes",
+                                        set.subject.color
+                                    ))
+                                    .child(img("/assets/study.svg", "study").size(24, 24))
+                                    .child(p("study")),
+                            )
+                            .child(
+                                a(format!("/edit-set/{}", set.id))
+                                    .class(format!(
+                                        "btn input-{} w-full sound-yes",
+                                        set.subject.color
+                                    ))
+                                    .child(img("/assets/edit.svg", "edit").size(24, 24))
+                                    .child(p("edit")),
+                            ),
+                    )
+            }))
     }
 }
 
@@ -0,0 +1,496 @@
+//! CSV and TSV files: `/import/csv` makes a set from one, with its columns mapped
+//! onto card fields, and `/sets/{id}/export.csv` (or `.tsv`) writes a set out as
+//! one. Fields are quoted RFC 4180 style, so definitions can span lines, and an
+//! exported set imports back exactly as it was.
+use crate::components;
+use crate::data::{self, body_to_string, Card, Query, ResourceError, Set};
+use crate::editor;
+use crate::import;
+use crate::store::Store;
+use html_builder::prelude::*;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Request, Response};
+use std::collections::HashSet;
+use std::fmt::Display;
+use uuid::Uuid;
+
+/// How many cards the preview shows.
+const PREVIEW_CARDS: usize = 5;
+
+/// A line of the file (from 1) and its fields, or why they couldn't be read.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Row {
+    pub line: usize,
+    pub fields: Result<Vec<String>, String>,
+}
+
+/// Splits the text into rows of fields. Quoted fields can hold the delimiter, line
+/// breaks and doubled quotes; a row with a quote problem is reported and skipped.
+pub fn parse(text: &str, delimiter: char) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut chars = text
+        .strip_prefix('\u{feff}')
+        .unwrap_or(text)
+        .chars()
+        .peekable();
+    let mut line = 1;
+    while chars.peek().is_some() {
+        let start = line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut error = None;
+        loop {
+            match chars.next() {
+                None => {
+                    fields.push(std::mem::take(&mut field));
+                    break;
+                }
+                Some('\n') => {
+                    line += 1;
+                    fields.push(std::mem::take(&mut field));
+                    break;
+                }
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some(character) if character == delimiter => {
+                    fields.push(std::mem::take(&mut field));
+                }
+                Some('"') if field.is_empty() => {
+                    loop {
+                        match chars.next() {
+                            None => {
+                                error = Some("a quote is never closed");
+                                break;
+                            }
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(character) => {
+                                if character == '\n' {
+                                    line += 1;
+                                }
+                                field.push(character);
+                            }
+                        }
+                    }
+                    if !matches!(chars.peek(), None | Some('\n' | '\r'))
+                        && chars.peek() != Some(&delimiter)
+                    {
+                        error = Some("there is more after a closing quote");
+                    }
+                    if error.is_some() {
+                        for character in chars.by_ref() {
+                            if character == '\n' {
+                                line += 1;
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                }
+                Some(character) => field.push(character),
+            }
+        }
+        rows.push(Row {
+            line: start,
+            fields: error.map_or(Ok(fields), |error| Err(error.to_string())),
+        });
+    }
+    rows
+}
+
+/// The rows as delimited text, quoting any field that needs it.
+pub fn write(rows: impl IntoIterator<Item = Vec<String>>, delimiter: char) -> String {
+    let mut text = String::new();
+    for row in rows {
+        let fields = row
+            .iter()
+            .map(|field| {
+                if field.contains([delimiter, '"', '\n', '\r']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        text.push_str(&fields.join(&delimiter.to_string()));
+        text.push_str("\r\n");
+    }
+    text
+}
+
+/// A card field a column can be imported into and exported from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Id,
+    Term,
+    Definition,
+}
+
+impl Field {
+    /// In the order they are exported.
+    pub const fn all() -> [Self; 3] {
+        [Self::Id, Self::Term, Self::Definition]
+    }
+
+    /// Other headers the field's column is recognised by.
+    const fn aliases(self) -> &'static [&'static str] {
+        match self {
+            Self::Id => &[],
+            Self::Term => &["front", "question", "word"],
+            Self::Definition => &["back", "answer", "meaning"],
+        }
+    }
+
+    fn get(self, card: &Card) -> &str {
+        match self {
+            Self::Id => &card.id,
+            Self::Term => &card.term,
+            Self::Definition => &card.definition,
+        }
+    }
+
+    fn set(self, card: &mut Card, value: String) {
+        match self {
+            Self::Id => card.id = value,
+            Self::Term => card.term = value,
+            Self::Definition => card.definition = value,
+        }
+    }
+
+    fn matches(self, header: &str) -> bool {
+        let header = header.trim().to_lowercase();
+        header == self.to_string() || self.aliases().contains(&header.as_str())
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Id => "id",
+            Self::Term => "term",
+            Self::Definition => "definition",
+        };
+        write!(f, "{string}")
+    }
+}
+
+/// The delimiter picked in the form, or if none is, tab for `.tsv` files and
+/// otherwise whichever of comma, semicolon and tab the first line has most of.
+fn delimiter(form: &Query, text: &str) -> char {
+    match form.get("delimiter").as_deref() {
+        Ok("comma") => ',',
+        Ok("tab") => '\t',
+        Ok("semicolon") => ';',
+        _ if form
+            .get("filename")
+            .is_ok_and(|name| name.to_lowercase().ends_with(".tsv")) =>
+        {
+            '\t'
+        }
+        _ => {
+            let first = text.lines().next().unwrap_or_default();
+            // the last of any tied is picked, so a line without any is split at commas
+            ['\t', ';', ',']
+                .into_iter()
+                .max_by_key(|delimiter| first.matches(*delimiter).count())
+                .unwrap_or(',')
+        }
+    }
+}
+
+/// What importing a file would make.
+#[derive(Clone, Debug, Default)]
+pub struct Import {
+    /// The file's header, if its first row is one.
+    pub header: Option<Vec<String>>,
+    /// Which column (from 0) each field is read from.
+    pub columns: Vec<(Field, usize)>,
+    pub cards: Vec<Card>,
+    /// The rows that couldn't be imported and why.
+    pub errors: Vec<String>,
+}
+
+/// Reads cards out of the file with the columns mapped in the form, where each
+/// `{field}-column` is a column's header or number (from 1). Fields that aren't
+/// mapped are found by their header, or are the first two columns if there is
+/// no header.
+fn read(form: &Query, text: &str) -> Result<Import, String> {
+    let mut rows = parse(text, delimiter(form, text)).into_iter().peekable();
+    let header = rows
+        .next_if(|row| {
+            row.fields.as_ref().is_ok_and(|fields| {
+                fields
+                    .iter()
+                    .any(|field| Field::all().iter().any(|known| known.matches(field)))
+            })
+        })
+        .and_then(|row| row.fields.ok());
+    let mut import = Import {
+        header,
+        ..Import::default()
+    };
+    for field in Field::all() {
+        let mapped = form.get(&format!("{field}-column")).unwrap_or_default();
+        let column = if mapped.trim().is_empty() {
+            match &import.header {
+                Some(header) => header.iter().position(|name| field.matches(name)),
+                None => match field {
+                    Field::Id => None,
+                    Field::Term => Some(0),
+                    Field::Definition => Some(1),
+                },
+            }
+        } else if let Ok(number @ 1..) = mapped.trim().parse::<usize>() {
+            Some(number - 1)
+        } else {
+            let position = import.header.as_ref().and_then(|header| {
+                header
+                    .iter()
+                    .position(|name| name.trim().eq_ignore_ascii_case(mapped.trim()))
+            });
+            Some(position.ok_or_else(|| format!("there isn't a column called \"{mapped}\""))?)
+        };
+        if let Some(column) = column {
+            import.columns.push((field, column));
+        }
+    }
+    if !import
+        .columns
+        .iter()
+        .any(|(field, _)| *field == Field::Term)
+    {
+        return Err("pick the column the terms are in".to_string());
+    }
+    let mut ids = HashSet::new();
+    for row in rows {
+        let fields = match row.fields {
+            Ok(fields) if fields.iter().all(String::is_empty) => continue,
+            Ok(fields) => fields,
+            Err(error) => {
+                import.errors.push(format!("line {}: {error}", row.line));
+                continue;
+            }
+        };
+        match card(&import.columns, fields, &mut ids) {
+            Ok(card) => import.cards.push(card),
+            Err(error) => import.errors.push(format!("line {}: {error}", row.line)),
+        }
+    }
+    Ok(import)
+}
+
+fn card(
+    columns: &[(Field, usize)],
+    mut fields: Vec<String>,
+    ids: &mut HashSet<String>,
+) -> Result<Card, String> {
+    let mut card = Card {
+        id: String::new(),
+        term: String::new(),
+        definition: String::new(),
+    };
+    for (field, column) in columns {
+        let value = fields
+            .get_mut(*column)
+            .map(std::mem::take)
+            .ok_or_else(|| format!("there is no column {} for the {field}", column + 1))?;
+        field.set(&mut card, value);
+    }
+    if card.term.trim().is_empty() {
+        return Err("the term is empty".to_string());
+    }
+    if card.definition.trim().is_empty() && !card.is_cloze() {
+        return Err("the definition is empty".to_string());
+    }
+    if card.id.is_empty() {
+        card.id = Uuid::new_v4().to_string();
+    }
+    if !ids.insert(card.id.clone()) {
+        return Err(format!("the id {} is used twice", card.id));
+    }
+    Ok(card)
+}
+
+/// `/import/csv`.
+pub async fn page(store: &dyn Store) -> Result<Html, ResourceError> {
+    let subjects = store.subjects().await?;
+    Ok(components::page(
+        "import a spreadsheet - flopcards",
+        body()
+            .child(h1("Import a spreadsheet"))
+            .child(p(
+                "upload a CSV or TSV file, with a column for terms and one for definitions",
+            ))
+            .child(components::csv_form(&subjects)),
+    ))
+}
+
+/// Handles `POST /view/csv-preview?...`, whose body is the file, answering with
+/// the columns the cards would be read from and the first few of them.
+pub async fn preview(request: Request<hyper::body::Incoming>) -> Result<Div, ResourceError> {
+    let form = Query::from_request(&request);
+    let text = body_to_string(request).await?;
+    Ok(match read(&form, &text) {
+        Ok(import) => components::csv_preview(&import, PREVIEW_CARDS),
+        Err(message) => div().child(p(message).class("text-red-500")),
+    })
+}
+
+/// Handles `POST /import/csv?subject=&title=&...`, whose body is the file, making
+/// a set of the rows that could be read and reporting the rest.
+pub async fn upload(
+    request: Request<hyper::body::Incoming>,
+    store: &dyn Store,
+) -> Result<Div, ResourceError> {
+    let form = Query::from_request(&request);
+    let text = body_to_string(request).await?;
+    import(&form, &text, store).await
+}
+
+async fn import(form: &Query, text: &str, store: &dyn Store) -> Result<Div, ResourceError> {
+    let subject = form.get("subject")?;
+    let subject = store
+        .subjects()
+        .await?
+        .into_iter()
+        .find(|found| found.id == subject)
+        .ok_or_else(|| ResourceError::NotFound(format!("/?subject={subject}")))?;
+    let mut import = match read(form, text) {
+        Ok(import) if import.cards.is_empty() => {
+            return Ok(components::csv_report(None, &import.errors))
+        }
+        Ok(import) => import,
+        Err(message) => return Ok(div().child(p(message).class("text-red-500"))),
+    };
+    import::fresh_card_ids(&mut import.cards, &mut import::card_ids(store).await?);
+    let title = form
+        .get("title")
+        .ok()
+        .filter(|title| !title.trim().is_empty())
+        .or_else(|| {
+            form.get("filename").ok().map(|name| {
+                name.rsplit_once('.')
+                    .map_or(name.as_str(), |(stem, _)| stem)
+                    .to_string()
+            })
+        })
+        .unwrap_or_else(|| "imported".to_string());
+    let set = Set {
+        id: editor::unique_id(store, &subject.id, &title).await?,
+        title: title.trim().to_string(),
+        description: String::new(),
+        subject,
+        folder: None,
+        remix_of: None,
+        algorithm: None,
+        cards: import.cards,
+    };
+    store.save_set(&set).await?;
+    Ok(components::csv_report(Some(&set), &import.errors))
+}
+
+/// `/sets/{id}/export.csv` or `.tsv`, with a header and a row for each card.
+pub async fn export(
+    id: &str,
+    delimiter: char,
+    store: &dyn Store,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let set = store.get_set(id).await?;
+    let header = Field::all().map(|field| field.to_string()).to_vec();
+    let rows = set.cards.iter().map(|card| {
+        Field::all()
+            .map(|field| field.get(card).to_string())
+            .to_vec()
+    });
+    let (extension, content_type) = if delimiter == '\t' {
+        ("tsv", "text/tab-separated-values; charset=utf-8")
+    } else {
+        ("csv", "text/csv; charset=utf-8")
+    };
+    data::attachment(
+        &format!("{}.{extension}", editor::slug(&set.title)),
+        content_type,
+        write(std::iter::once(header).chain(rows), delimiter).into_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::fixtures::{algebra, maths};
+    use crate::store::MemoryStore;
+    use http_body_util::BodyExt;
+
+    fn rows(fields: &[&[&str]]) -> Vec<Vec<String>> {
+        fields
+            .iter()
+            .map(|row| row.iter().map(ToString::to_string).collect())
+            .collect()
+    }
+
+    #[test]
+    fn written_rows_parse_back_the_same() {
+        let written = rows(&[
+            &["term", "definition"],
+            &["comma, inside", "line\nbreak"],
+            &["\"quoted\"", ""],
+        ]);
+        for delimiter in [',', '\t', ';'] {
+            let parsed = parse(&write(written.clone(), delimiter), delimiter)
+                .into_iter()
+                .map(|row| row.fields.unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(parsed, written);
+        }
+    }
+
+    #[test]
+    fn parse_reports_quote_problems_with_their_line() {
+        let parsed = parse("a,\"b\nc\"\n\"d\"e,f\ng,\"h", ',');
+        assert_eq!(
+            parsed[0].fields,
+            Ok(vec!["a".to_string(), "b\nc".to_string()])
+        );
+        assert_eq!(
+            parsed[1],
+            Row {
+                line: 3,
+                fields: Err("there is more after a closing quote".to_string()),
+            }
+        );
+        assert_eq!(parsed[2].line, 4);
+        assert_eq!(parsed[2].fields, Err("a quote is never closed".to_string()));
+    }
+
+    #[tokio::test]
+    async fn exported_sets_import_as_copies() {
+        let mut algebra = algebra();
+        algebra.cards.push(Card {
+            id: "expand".to_string(),
+            term: "(a + b)²".to_string(),
+            definition: "a² + 2ab + b²,\nnot a² + b²".to_string(),
+        });
+        let store = MemoryStore::new(vec![maths()], Vec::new(), [algebra.clone()]);
+        let response = export("maths/algebra", ',', &store).await.unwrap();
+        let text = response.into_body().collect().await.unwrap().to_bytes();
+        let form = Query::from_str("subject=maths&title=algebra+again");
+        import(&form, std::str::from_utf8(&text).unwrap(), &store)
+            .await
+            .unwrap();
+
+        let copy = store.get_set("maths/algebra-again").await.unwrap();
+        let terms = |set: &Set| {
+            set.cards
+                .iter()
+                .map(|card| (card.term.clone(), card.definition.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(terms(&copy), terms(&algebra));
+        for (card, original) in copy.cards.iter().zip(&algebra.cards) {
+            assert_ne!(card.id, original.id);
+        }
+    }
+}
@@ -1,11 +1,15 @@
+use crate::cloze;
+use crate::scheduler::Algorithm;
 use futures::{StreamExt, TryStreamExt};
-use http::Request;
-use http_body_util::BodyExt;
+use http::{Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt::Display, fs, future::IntoFuture};
+use std::{collections::HashMap, fmt::Display, future::IntoFuture, str::FromStr};
 use uuid::Uuid;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Rating {
     Terrible,
     Bad,
@@ -39,27 +43,186 @@ impl Display for Rating {
     }
 }
 
+impl FromStr for Rating {
+    type Err = ResourceError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .into_iter()
+            .find(|rating| rating.to_string() == string)
+            .ok_or_else(|| ResourceError::Custom(format!("'{string}' isn't a rating")))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Card {
+    /// Stable id used to key review state, derived from the set and term if not given.
+    #[serde(default)]
+    pub id: String,
+    /// For a cloze card, the text with its `{{c1::...}}` markers.
     pub term: String,
+    /// Optional for a cloze card.
+    #[serde(default)]
     pub definition: String,
 }
 
+/// One side of a card.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Side {
+    Term,
+    #[default]
+    Definition,
+}
+
+impl Side {
+    pub const fn other(self) -> Self {
+        match self {
+            Self::Term => Self::Definition,
+            Self::Definition => Self::Term,
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Term => "term",
+            Self::Definition => "definition",
+        };
+        write!(f, "{string}")
+    }
+}
+
+impl FromStr for Side {
+    type Err = ResourceError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "term" => Ok(Self::Term),
+            "definition" => Ok(Self::Definition),
+            _ => Err(ResourceError::Custom(format!(
+                "'{string}' isn't a side of a card"
+            ))),
+        }
+    }
+}
+
+/// Which way round a set's cards are studied.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Term first, definition on the flip.
+    #[default]
+    Forward,
+    /// Definition first, term on the flip.
+    Reverse,
+    /// Each card once each way round, scheduled separately.
+    Both,
+}
+
+impl Direction {
+    pub const fn all() -> [Self; 3] {
+        [Self::Forward, Self::Reverse, Self::Both]
+    }
+
+    /// The sides shown first when studying in this direction.
+    pub const fn fronts(self) -> &'static [Side] {
+        match self {
+            Self::Forward => &[Side::Term],
+            Self::Reverse => &[Side::Definition],
+            Self::Both => &[Side::Term, Side::Definition],
+        }
+    }
+}
+
+impl From<Side> for Direction {
+    /// The direction that shows `front` first.
+    fn from(front: Side) -> Self {
+        match front {
+            Side::Term => Self::Forward,
+            Side::Definition => Self::Reverse,
+        }
+    }
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Forward => "forward",
+            Self::Reverse => "reverse",
+            Self::Both => "both",
+        };
+        write!(f, "{string}")
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ResourceError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .into_iter()
+            .find(|direction| direction.to_string() == string)
+            .ok_or_else(|| ResourceError::Custom(format!("'{string}' isn't a direction")))
+    }
+}
+
+impl Card {
+    pub fn side(&self, side: Side) -> &str {
+        match side {
+            Side::Term => &self.term,
+            Side::Definition => &self.definition,
+        }
+    }
+
+    /// Whether the term has cloze markers, making it one item per cloze number.
+    pub fn is_cloze(&self) -> bool {
+        !cloze::numbers(&self.term).is_empty()
+    }
+
+    /// The id the card's review state is kept under when it is studied with `front`
+    /// shown first. Term-first keeps the plain card id so existing progress carries over.
+    pub fn review_id(&self, front: Side) -> String {
+        match front {
+            Side::Term => self.id.clone(),
+            Side::Definition => format!("{}/reverse", self.id),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Subject {
+    pub id: String,
     pub name: String,
     pub color: String,
 }
 
-impl Subject {
-    pub fn fetch_all() -> Vec<Self> {
-        macros::subjects!()
-            .into_iter()
-            .map(|(name, color)| Self {
-                name: name.to_string(),
-                color: color.to_string(),
-            })
-            .collect()
+/// A directory of sets (and other folders) within a subject.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Folder {
+    pub id: String,
+    pub title: String,
+    pub subject: String,
+    /// The enclosing folder, or `None` if the folder is at the top of its subject.
+    pub parent: Option<String>,
+}
+
+impl Folder {
+    /// The chain of folders from the top of the subject down to `id`, inclusive.
+    pub fn breadcrumbs(folders: &[Self], id: &str) -> Vec<Self> {
+        let mut breadcrumbs = Vec::new();
+        let mut next = Some(id);
+        while let Some(folder) = next.and_then(|id| folders.iter().find(|folder| folder.id == id)) {
+            // guards against a folder being its own ancestor
+            if breadcrumbs.contains(folder) {
+                break;
+            }
+            breadcrumbs.push(folder.clone());
+            next = folder.parent.as_deref();
+        }
+        breadcrumbs.reverse();
+        breadcrumbs
     }
 }
 
@@ -73,58 +236,134 @@ pub enum ResourceError {
     ParseJson(#[from] serde_json::Error),
     #[error("database didn't want to see a trick that's worth it: {0}")]
     ParseToml(#[from] toml::de::Error),
+    #[error("couldn't write that trick down: {0}")]
+    SerializeToml(#[from] toml::ser::Error),
     #[error("path '{0}' couldn't be found. maybe it fell out of a coconut tree?")]
     NotFound(String),
+    #[error("database is stuck in a coconut tree: {0}")]
+    Database(#[from] libsql::Error),
     #[error("{0}")]
     Custom(String),
     #[error("file system couldn't find the file (where it's at?)")]
     Io(#[from] std::io::Error),
+    #[error("package got squashed like a sardeen: {0}")]
+    Zip(#[from] zip::result::ZipError),
 }
 
-pub async fn body_to_string(
+impl ResourceError {
+    /// Missing things are not found and custom errors are mistakes in the request.
+    /// Anything else is the server's fault.
+    pub const fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Custom(_) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// The error as a plain text response with its status code.
+    pub fn response(&self) -> Response<Full<Bytes>> {
+        Response::builder()
+            .status(self.status())
+            .header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Full::new(Bytes::from(self.to_string())))
+            .unwrap()
+    }
+}
+
+pub async fn body_to_bytes(
     request: Request<hyper::body::Incoming>,
-) -> Result<String, ResourceError> {
-    let body = request
+) -> Result<Vec<u8>, ResourceError> {
+    Ok(request
         .into_body()
         .collect()
         .into_future()
         .await?
         .to_bytes()
-        .to_vec();
+        .to_vec())
+}
+
+/// A response that has the browser save `body` as a file called `file_name`.
+pub fn attachment(
+    file_name: &str,
+    content_type: &str,
+    body: Vec<u8>,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    Ok(Response::builder()
+        .header(http::header::CONTENT_TYPE, content_type)
+        .header(
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{file_name}\""),
+        )
+        .body(Full::new(Bytes::from(body)))
+        .unwrap())
+}
+
+pub async fn body_to_string(
+    request: Request<hyper::body::Incoming>,
+) -> Result<String, ResourceError> {
+    let body = body_to_bytes(request).await?;
     Ok(String::from_utf8_lossy(&body).to_string())
 }
 
 #[derive(Clone, Debug, Deserialize)]
 pub struct Set {
-    pub path: String,
+    #[serde(default)]
+    pub id: String,
     pub title: String,
     pub description: String,
     pub subject: Subject,
+    /// The folder the set is in, or `None` if it is at the top of its subject.
+    #[serde(default)]
+    pub folder: Option<String>,
+    /// The set this one was copied from with "create remix".
+    #[serde(default)]
+    pub remix_of: Option<String>,
+    /// Overrides the scheduling algorithm chosen in the learner's progress.
+    #[serde(default)]
+    pub algorithm: Option<Algorithm>,
     pub cards: Vec<Card>,
 }
 
 impl Set {
-    pub fn get(path: &str) -> Result<Self, ResourceError> {
-        let config = fs::read_to_string(path)?;
-        Ok(toml::from_str(&config)?)
-    }
-
-    pub fn fetch_all(subject: &str) -> Result<Vec<Self>, ResourceError> {
-        let mut sets = Vec::new();
-        for entry in fs::read_dir(format!("./flashcards/{subject}"))? {
-            let entry = entry.unwrap();
-            let name = entry.file_name().into_string().unwrap();
-            if !(entry.file_type()?.is_file() && name.ends_with(".toml")) {
-                continue;
+    /// The cards as they are studied: cards as they are, except cloze cards which
+    /// become one card per cloze number, with that cloze blanked in the term and its
+    /// words as the definition.
+    pub fn items(&self) -> Vec<Card> {
+        self.cards
+            .iter()
+            .flat_map(|card| {
+                let numbers = cloze::numbers(&card.term);
+                if numbers.is_empty() {
+                    return vec![card.clone()];
+                }
+                numbers
+                    .into_iter()
+                    .map(|number| {
+                        let (term, definition) = cloze::render(&card.term, number);
+                        Card {
+                            id: cloze::item_id(&card.id, number),
+                            term,
+                            definition,
+                        }
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Gives any card without an id one derived from the set id and its term, so
+    /// that review state survives the set being reloaded.
+    pub fn assign_card_ids(&mut self) {
+        for card in &mut self.cards {
+            if card.id.is_empty() {
+                let name = format!("{}\n{}", self.id, card.term.trim());
+                card.id = Uuid::new_v5(&Uuid::NAMESPACE_OID, name.as_bytes()).to_string();
             }
-            sets.push(Self::get(&format!("./flashcards/{subject}/{name}"))?);
         }
-        Ok(sets)
     }
 }
-/*
-SELECT cardset.id, cardset.title, cardset.description, cardset.parent, cardset.created, subject.id, subject.name, subject.color FROM cardset WHERE subject = 'geography' INNER JOIN subject ON cardset.subject=subject.id;
- */
+
 #[derive(Clone, Debug, Default)]
 pub struct Query(pub HashMap<String, String>);
 
@@ -151,4 +390,10 @@ impl Query {
             .ok_or_else(|| ResourceError::Custom(format!("Expected query param '{key}'")))
             .map(ToString::to_string)
     }
+
+    pub fn parse<T: FromStr>(&self, key: &str) -> Result<T, ResourceError> {
+        self.get(key)?
+            .parse()
+            .map_err(|_| ResourceError::Custom(format!("Couldn't understand query param '{key}'")))
+    }
 }
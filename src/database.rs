@@ -0,0 +1,531 @@
+use crate::data::{Card, Folder, ResourceError, Set, Subject};
+use crate::scheduler::ReviewLog;
+use libsql::{params, Builder, Connection};
+
+const SCHEMA: &str = include_str!("../schema.sql");
+
+/// A change made to the schema after databases had already been created from it.
+enum Migration {
+    /// Adds a column to a table, given as the table, the column and its type.
+    AddColumn(&'static str, &'static str, &'static str),
+    Execute(&'static str),
+}
+
+/// Every change to the schema since the first databases were created, oldest first.
+/// A database's `user_version` is how many of them it has had, and `schema.sql`
+/// already includes all of them.
+const MIGRATIONS: &[Migration] = &[
+    // folders inside folders
+    Migration::AddColumn("folder", "parent", "TEXT REFERENCES folder (id)"),
+    // remixes
+    Migration::AddColumn("cardset", "remix_of", "TEXT REFERENCES cardset (id)"),
+    // the review log
+    Migration::Execute(
+        "CREATE TABLE IF NOT EXISTS review (
+           id INTEGER PRIMARY KEY,
+           cardset TEXT NOT NULL,
+           card TEXT NOT NULL,
+           rating TEXT NOT NULL,
+           response_time INTEGER NOT NULL,
+           created INTEGER NOT NULL
+         )",
+    ),
+];
+
+/// Sets, subjects and cards stored in a libsql database laid out by `schema.sql`.
+#[derive(Clone)]
+pub struct Database {
+    connection: Connection,
+}
+
+impl Database {
+    /// Opens (or creates) a local SQLite file, creating the tables if they are missing.
+    pub async fn open_local(path: &str) -> Result<Self, ResourceError> {
+        let connection = Builder::new_local(path).build().await?.connect()?;
+        Self::new(connection).await
+    }
+
+    /// Connects to a database hosted by Turso, creating the tables if they are missing.
+    pub async fn open_remote(url: &str, token: &str) -> Result<Self, ResourceError> {
+        let connection = Builder::new_remote(url.to_string(), token.to_string())
+            .build()
+            .await?
+            .connect()?;
+        Self::new(connection).await
+    }
+
+    pub async fn new(connection: Connection) -> Result<Self, ResourceError> {
+        let database = Self { connection };
+        database.migrate().await?;
+        Ok(database)
+    }
+
+    /// Creates the tables if there aren't any, or brings them up to date.
+    async fn migrate(&self) -> Result<(), ResourceError> {
+        let mut tables = self
+            .connection
+            .query(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'cardset'",
+                (),
+            )
+            .await?;
+        if tables.next().await?.is_none() {
+            self.connection.execute_batch(SCHEMA).await?;
+            return self.set_version(MIGRATIONS.len()).await;
+        }
+        let mut rows = self.connection.query("PRAGMA user_version", ()).await?;
+        let version = match rows.next().await? {
+            Some(row) => usize::try_from(row.get::<i64>(0)?).unwrap_or_default(),
+            None => 0,
+        };
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            match migration {
+                Migration::AddColumn(table, column, kind) => {
+                    // databases from before versions were kept may have it already
+                    if !self.has_column(table, column).await? {
+                        self.connection
+                            .execute(
+                                &format!("ALTER TABLE {table} ADD COLUMN {column} {kind}"),
+                                (),
+                            )
+                            .await?;
+                    }
+                }
+                Migration::Execute(sql) => {
+                    self.connection.execute(sql, ()).await?;
+                }
+            }
+            self.set_version(index + 1).await?;
+        }
+        Ok(())
+    }
+
+    async fn set_version(&self, version: usize) -> Result<(), ResourceError> {
+        self.connection
+            .execute(&format!("PRAGMA user_version = {version}"), ())
+            .await?;
+        Ok(())
+    }
+
+    async fn has_column(&self, table: &str, column: &str) -> Result<bool, ResourceError> {
+        let mut rows = self
+            .connection
+            .query(
+                "SELECT name FROM pragma_table_info(?1) WHERE name = ?2",
+                params![table, column],
+            )
+            .await?;
+        Ok(rows.next().await?.is_some())
+    }
+
+    pub async fn fetch_subjects(&self) -> Result<Vec<Subject>, ResourceError> {
+        let mut rows = self
+            .connection
+            .query("SELECT id, name, color FROM subject ORDER BY name", ())
+            .await?;
+        let mut subjects = Vec::new();
+        while let Some(row) = rows.next().await? {
+            subjects.push(Subject {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                color: row.get(2)?,
+            });
+        }
+        Ok(subjects)
+    }
+
+    pub async fn fetch_folders(&self, subject: &str) -> Result<Vec<Folder>, ResourceError> {
+        let mut rows = self
+            .connection
+            .query(
+                "SELECT id, title, subject, parent FROM folder WHERE subject = ?1 ORDER BY id",
+                params![subject],
+            )
+            .await?;
+        let mut folders = Vec::new();
+        while let Some(row) = rows.next().await? {
+            folders.push(Folder {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                subject: row.get(2)?,
+                parent: row.get(3)?,
+            });
+        }
+        Ok(folders)
+    }
+
+    pub async fn fetch_sets(&self, subject: &str) -> Result<Vec<Set>, ResourceError> {
+        self.query_sets(
+            "WHERE cardset.subject = ?1 ORDER BY cardset.created",
+            subject,
+        )
+        .await
+    }
+
+    pub async fn get_set(&self, id: &str) -> Result<Set, ResourceError> {
+        self.query_sets("WHERE cardset.id = ?1", id)
+            .await?
+            .pop()
+            .ok_or_else(|| ResourceError::NotFound(format!("/sets/{id}")))
+    }
+
+    pub async fn fetch_remixes(&self, id: &str) -> Result<Vec<Set>, ResourceError> {
+        self.query_sets("WHERE cardset.remix_of = ?1 ORDER BY cardset.created", id)
+            .await
+    }
+
+    async fn query_sets(&self, filter: &str, param: &str) -> Result<Vec<Set>, ResourceError> {
+        let mut rows = self
+            .connection
+            .query(
+                &format!(
+                    "SELECT cardset.id, cardset.title, cardset.description, cardset.algorithm, \
+                     cardset.parent, cardset.remix_of, subject.id, subject.name, subject.color \
+                     FROM cardset INNER JOIN subject ON cardset.subject = subject.id {filter}"
+                ),
+                params![param],
+            )
+            .await?;
+        let mut sets = Vec::new();
+        while let Some(row) = rows.next().await? {
+            let algorithm = row.get::<Option<String>>(3)?;
+            sets.push(Set {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                description: row.get(2)?,
+                algorithm: algorithm.map(|algorithm| algorithm.parse()).transpose()?,
+                folder: row.get(4)?,
+                remix_of: row.get(5)?,
+                subject: Subject {
+                    id: row.get(6)?,
+                    name: row.get(7)?,
+                    color: row.get(8)?,
+                },
+                cards: Vec::new(),
+            });
+        }
+        for set in &mut sets {
+            set.cards = self.fetch_cards(&set.id).await?;
+        }
+        Ok(sets)
+    }
+
+    async fn fetch_cards(&self, set: &str) -> Result<Vec<Card>, ResourceError> {
+        let mut rows = self
+            .connection
+            .query(
+                "SELECT id, term, definition FROM card WHERE cardset = ?1 ORDER BY position",
+                params![set],
+            )
+            .await?;
+        let mut cards = Vec::new();
+        while let Some(row) = rows.next().await? {
+            cards.push(Card {
+                id: row.get(0)?,
+                term: row.get(1)?,
+                definition: row.get(2)?,
+            });
+        }
+        Ok(cards)
+    }
+
+    /// Inserts the set or overwrites the existing one with the same id, replacing
+    /// all of its cards.
+    pub async fn save_set(&self, set: &Set) -> Result<(), ResourceError> {
+        let transaction = self.connection.transaction().await?;
+        transaction
+            .execute(
+                "INSERT INTO cardset (id, title, description, subject, parent, algorithm, remix_of) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+                 ON CONFLICT (id) DO UPDATE SET title = excluded.title, \
+                 description = excluded.description, subject = excluded.subject, \
+                 parent = excluded.parent, algorithm = excluded.algorithm, \
+                 remix_of = excluded.remix_of",
+                params![
+                    set.id.as_str(),
+                    set.title.as_str(),
+                    set.description.as_str(),
+                    set.subject.id.as_str(),
+                    set.folder.as_deref(),
+                    set.algorithm.map(|algorithm| algorithm.to_string()),
+                    set.remix_of.as_deref(),
+                ],
+            )
+            .await?;
+        transaction
+            .execute(
+                "DELETE FROM card WHERE cardset = ?1",
+                params![set.id.as_str()],
+            )
+            .await?;
+        for (position, card) in (0_i64..).zip(&set.cards) {
+            transaction
+                .execute(
+                    "INSERT INTO card (id, term, definition, cardset, position) \
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        card.id.as_str(),
+                        card.term.as_str(),
+                        card.definition.as_str(),
+                        set.id.as_str(),
+                        position,
+                    ],
+                )
+                .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    pub async fn has_set(&self, id: &str) -> Result<bool, ResourceError> {
+        let mut rows = self
+            .connection
+            .query("SELECT id FROM cardset WHERE id = ?1", params![id])
+            .await?;
+        Ok(rows.next().await?.is_some())
+    }
+
+    /// Inserts the subject, or sets its colour if it already exists. Returns the
+    /// colour it had before, or `None` if it was inserted.
+    pub async fn save_subject(&self, subject: &Subject) -> Result<Option<String>, ResourceError> {
+        let mut rows = self
+            .connection
+            .query(
+                "SELECT color FROM subject WHERE id = ?1",
+                params![subject.id.as_str()],
+            )
+            .await?;
+        let previous = match rows.next().await? {
+            Some(row) => Some(row.get::<String>(0)?),
+            None => None,
+        };
+        self.connection
+            .execute(
+                "INSERT INTO subject (id, name, color) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT (id) DO UPDATE SET color = excluded.color",
+                params![
+                    subject.id.as_str(),
+                    subject.name.as_str(),
+                    subject.color.as_str()
+                ],
+            )
+            .await?;
+        Ok(previous)
+    }
+
+    /// Returns whether the folder was inserted, or `false` if it already existed.
+    pub async fn insert_folder(&self, folder: &Folder) -> Result<bool, ResourceError> {
+        let inserted = self
+            .connection
+            .execute(
+                "INSERT OR IGNORE INTO folder (id, title, description, subject, parent) \
+                 VALUES (?1, ?2, '', ?3, ?4)",
+                params![
+                    folder.id.as_str(),
+                    folder.title.as_str(),
+                    folder.subject.as_str(),
+                    folder.parent.as_deref()
+                ],
+            )
+            .await?;
+        Ok(inserted > 0)
+    }
+
+    pub async fn insert_review(&self, review: &ReviewLog) -> Result<(), ResourceError> {
+        self.connection
+            .execute(
+                "INSERT INTO review (cardset, card, rating, response_time, created) \
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    review.set.as_str(),
+                    review.card.as_str(),
+                    review.rating.to_string(),
+                    i64::try_from(review.response_time).unwrap_or(i64::MAX),
+                    review.timestamp,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Every review, oldest first.
+    pub async fn fetch_reviews(&self) -> Result<Vec<ReviewLog>, ResourceError> {
+        let mut rows = self
+            .connection
+            .query(
+                "SELECT cardset, card, rating, response_time, created FROM review \
+                 ORDER BY created, id",
+                (),
+            )
+            .await?;
+        let mut reviews = Vec::new();
+        while let Some(row) = rows.next().await? {
+            reviews.push(ReviewLog {
+                set: row.get(0)?,
+                card: row.get(1)?,
+                rating: row.get::<String>(2)?.parse()?,
+                response_time: u64::try_from(row.get::<i64>(3)?).unwrap_or_default(),
+                timestamp: row.get(4)?,
+            });
+        }
+        Ok(reviews)
+    }
+
+    pub async fn delete_set(&self, id: &str) -> Result<(), ResourceError> {
+        let transaction = self.connection.transaction().await?;
+        transaction
+            .execute("DELETE FROM card WHERE cardset = ?1", params![id])
+            .await?;
+        let deleted = transaction
+            .execute("DELETE FROM cardset WHERE id = ?1", params![id])
+            .await?;
+        if deleted == 0 {
+            return Err(ResourceError::NotFound(format!("/sets/{id}")));
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Rating;
+    use crate::store::fixtures::algebra;
+    use uuid::Uuid;
+
+    /// A path for a database file that doesn't exist yet.
+    fn temp_path() -> String {
+        std::env::temp_dir()
+            .join(format!("flopcards-test-{}.db", Uuid::new_v4()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// [`algebra`] under `id`, with a card id of its own.
+    fn set(id: &str) -> Set {
+        let mut set = algebra();
+        set.id = id.to_string();
+        set.cards[0].id = Uuid::new_v4().to_string();
+        set
+    }
+
+    #[tokio::test]
+    async fn round_trips_sets() {
+        let path = temp_path();
+        let database = Database::open_local(&path).await.unwrap();
+        let mut set = set("maths/algebra");
+        database.save_set(&set).await.unwrap();
+        let saved = database.get_set(&set.id).await.unwrap();
+        assert_eq!(saved.title, set.title);
+        assert_eq!(saved.description, set.description);
+        assert_eq!(saved.subject, set.subject);
+        assert_eq!(saved.cards, set.cards);
+
+        // saving again replaces the cards rather than adding to them
+        set.cards.push(Card {
+            id: Uuid::new_v4().to_string(),
+            term: "x * x".to_string(),
+            definition: "x squared".to_string(),
+        });
+        set.algorithm = Some(crate::scheduler::Algorithm::Fsrs);
+        database.save_set(&set).await.unwrap();
+        let sets = database.fetch_sets("maths").await.unwrap();
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].cards, set.cards);
+        assert_eq!(sets[0].algorithm, set.algorithm);
+        assert!(database.fetch_sets("spanish").await.unwrap().is_empty());
+
+        database.delete_set(&set.id).await.unwrap();
+        assert!(matches!(
+            database.get_set(&set.id).await,
+            Err(ResourceError::NotFound(_))
+        ));
+        assert!(matches!(
+            database.delete_set(&set.id).await,
+            Err(ResourceError::NotFound(_))
+        ));
+        std::fs::remove_file(&path).ok();
+    }
+
+    fn review(card: &str, rating: Rating, timestamp: i64) -> ReviewLog {
+        ReviewLog {
+            set: "maths/algebra".to_string(),
+            card: card.to_string(),
+            rating,
+            response_time: 1500,
+            timestamp,
+        }
+    }
+
+    #[tokio::test]
+    async fn logs_reviews_in_order() {
+        let path = temp_path();
+        let database = Database::open_local(&path).await.unwrap();
+        let later = review("x/reverse", Rating::Bad, 200);
+        let earlier = review("x", Rating::Good, 100);
+        database.insert_review(&later).await.unwrap();
+        database.insert_review(&earlier).await.unwrap();
+        assert_eq!(database.fetch_reviews().await.unwrap(), [earlier, later]);
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn migrates_tables_from_before_folders_and_remixes() {
+        let path = temp_path();
+        let connection = Builder::new_local(&path)
+            .build()
+            .await
+            .unwrap()
+            .connect()
+            .unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE cardset (id TEXT PRIMARY KEY, title TEXT NOT NULL, \
+                 description TEXT NOT NULL, parent TEXT, \
+                 created DATETIME DEFAULT CURRENT_TIMESTAMP, subject TEXT NOT NULL, \
+                 algorithm TEXT); \
+                 CREATE TABLE folder (id TEXT PRIMARY KEY, title TEXT NOT NULL, \
+                 description TEXT NOT NULL, created DATETIME DEFAULT CURRENT_TIMESTAMP, \
+                 subject TEXT NOT NULL); \
+                 CREATE TABLE subject (id TEXT PRIMARY KEY, name TEXT NOT NULL, \
+                 color TEXT NOT NULL); \
+                 INSERT INTO subject VALUES ('maths', 'maths', 'red'); \
+                 CREATE TABLE card (id TEXT PRIMARY KEY, term TEXT NOT NULL, \
+                 definition TEXT NOT NULL, cardset TEXT NOT NULL, position INTEGER NOT NULL);",
+            )
+            .await
+            .unwrap();
+        drop(connection);
+
+        let database = Database::open_local(&path).await.unwrap();
+        let folder = Folder {
+            id: "maths/pure".to_string(),
+            title: "pure".to_string(),
+            subject: "maths".to_string(),
+            parent: None,
+        };
+        database.insert_folder(&folder).await.unwrap();
+        let original = set("maths/algebra");
+        // card ids are unique across every set, so the remix's cards get their own
+        let remix = Set {
+            remix_of: Some(original.id.clone()),
+            folder: Some(folder.id.clone()),
+            ..set("maths/algebra-remix")
+        };
+        database.save_set(&original).await.unwrap();
+        database.save_set(&remix).await.unwrap();
+        assert_eq!(database.fetch_folders("maths").await.unwrap(), [folder]);
+        let remixes = database.fetch_remixes(&original.id).await.unwrap();
+        assert_eq!(remixes.len(), 1);
+        assert_eq!(remixes[0].folder.as_deref(), Some("maths/pure"));
+        let logged = review("x", Rating::Ok, 100);
+        database.insert_review(&logged).await.unwrap();
+        assert_eq!(database.fetch_reviews().await.unwrap(), [logged]);
+
+        // opening it again doesn't try to add the columns a second time
+        drop(database);
+        Database::open_local(&path).await.unwrap();
+        std::fs::remove_file(&path).ok();
+    }
+}
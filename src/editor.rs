@@ -0,0 +1,310 @@
+//! `/create-set` and `/edit-set/{id}`. In the editor every change is saved through
+//! the store as soon as it is made, and answered with the part of the page it affects.
+use crate::cloze;
+use crate::components;
+use crate::data::{body_to_string, Card, Query, ResourceError, Set, Subject};
+use crate::store::Store;
+use html_builder::prelude::*;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Request, Response};
+use itertools::Itertools;
+use uuid::Uuid;
+
+pub async fn page(id: &str, store: &dyn Store) -> Result<Html, ResourceError> {
+    let set = store.get_set(id).await?;
+    Ok(components::page(
+        format!("editing {} - flopcards", set.title),
+        body()
+            .child(h1("Edit"))
+            .child(
+                a(format!("/sets/{}", set.id))
+                    .class("underline")
+                    .text("study"),
+            )
+            .children(set.remix_of.as_ref().map(|original| {
+                a(format!("/sets/{original}"))
+                    .class("underline")
+                    .text(format!("remix of {original}"))
+            }))
+            .child(components::set_details_form(&set))
+            .child(components::algorithm_picker(&set))
+            .child(components::card_editor_list(&set)),
+    ))
+}
+
+/// Handles the `POST`s made by the editor under `/edit-set/{path}`.
+pub async fn update(
+    request: Request<hyper::body::Incoming>,
+    path: &str,
+    store: &dyn Store,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let form = Query::from_str(&body_to_string(request).await?);
+    if let Some((id, algorithm)) = path.rsplit_once("/algorithm/") {
+        let mut set = store.get_set(id).await?;
+        set.algorithm = match algorithm {
+            "default" => None,
+            algorithm => Some(algorithm.parse()?),
+        };
+        store.save_set(&set).await?;
+        return components::algorithm_picker(&set).response_ok();
+    }
+    if let Some(id) = path.strip_suffix("/cards") {
+        let mut set = store.get_set(id).await?;
+        let term = non_empty(form.get("term")?, "term")?;
+        set.cards.push(Card {
+            id: Uuid::new_v4().to_string(),
+            definition: definition(&term, form.get("definition")?)?,
+            term,
+        });
+        store.save_set(&set).await?;
+        return components::card_editor_list(&set).response_ok();
+    }
+    let Some((id, card_path)) = path.rsplit_once("/cards/") else {
+        let mut set = store.get_set(path).await?;
+        set.title = non_empty(form.get("title")?, "title")?;
+        set.description = form.get("description")?;
+        store.save_set(&set).await?;
+        return components::set_details_form(&set).response_ok();
+    };
+    let mut set = store.get_set(id).await?;
+    let (card, action) = card_path.split_once('/').unwrap_or((card_path, ""));
+    let index = set
+        .cards
+        .iter()
+        .position(|set_card| set_card.id == card)
+        .ok_or_else(|| ResourceError::NotFound(format!("/edit-set/{path}")))?;
+    match action {
+        "" => {
+            let card = &mut set.cards[index];
+            card.term = non_empty(form.get("term")?, "term")?;
+            card.definition = definition(&card.term, form.get("definition")?)?;
+            store.save_set(&set).await?;
+            components::card_editor(&set.id, &set.cards[index]).response_ok()
+        }
+        "delete" => {
+            set.cards.remove(index);
+            store.save_set(&set).await?;
+            components::card_editor_list(&set).response_ok()
+        }
+        "up" | "down" => {
+            let other = if action == "up" {
+                index.checked_sub(1)
+            } else {
+                Some(index + 1).filter(|other| *other < set.cards.len())
+            };
+            if let Some(other) = other {
+                set.cards.swap(index, other);
+                store.save_set(&set).await?;
+            }
+            components::card_editor_list(&set).response_ok()
+        }
+        _ => Err(ResourceError::NotFound(format!("/edit-set/{path}"))),
+    }
+}
+
+/// Copies the set, keeping track of where it came from, and sends the browser to
+/// the copy's editor.
+pub async fn remix(id: &str, store: &dyn Store) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let original = store.get_set(id).await?;
+    let title = format!("{} (remix)", original.title);
+    let remix = Set {
+        id: unique_id(store, &original.subject.id, &title).await?,
+        title,
+        remix_of: Some(original.id.clone()),
+        cards: original
+            .cards
+            .into_iter()
+            .map(|card| Card {
+                id: Uuid::new_v4().to_string(),
+                ..card
+            })
+            .collect(),
+        ..original
+    };
+    store.save_set(&remix).await?;
+    redirect(&format!("/edit-set/{}", remix.id))
+}
+
+pub async fn remixes_page(id: &str, store: &dyn Store) -> Result<Html, ResourceError> {
+    let set = store.get_set(id).await?;
+    let remixes = store.remixes(id).await?;
+    Ok(components::page(
+        format!("remixes of {} - flopcards", set.title),
+        body()
+            .child(h1(format!("Remixes of {}", set.title)))
+            .child(components::set_list(None, &[], &[], &remixes)),
+    ))
+}
+
+pub async fn create_page(store: &dyn Store) -> Result<Html, ResourceError> {
+    let subjects = store.subjects().await?;
+    Ok(components::page(
+        "create set - flopcards",
+        body()
+            .child(h1("Create"))
+            .child(components::create_set_form(&subjects)),
+    ))
+}
+
+/// Validates the create-set form and writes the new set, sending the browser to
+/// its editor. Problems with the form are shown above the create button instead.
+pub async fn create(
+    request: Request<hyper::body::Incoming>,
+    store: &dyn Store,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let form = Query::from_str(&body_to_string(request).await?);
+    let subjects = store.subjects().await?;
+    let set = match parse_new_set(&form, &subjects) {
+        Ok(set) => set,
+        Err(message) => return p(message).response_ok(),
+    };
+    let set = Set {
+        id: unique_id(store, &set.subject.id, &set.title).await?,
+        ..set
+    };
+    store.save_set(&set).await?;
+    redirect(&format!("/edit-set/{}", set.id))
+}
+
+fn parse_new_set(form: &Query, subjects: &[Subject]) -> Result<Set, String> {
+    let subject = form
+        .get("subject")
+        .map_err(|_| "pick a subject".to_string())?;
+    let subject = subjects
+        .iter()
+        .find(|candidate| candidate.id == subject)
+        .ok_or_else(|| format!("'{subject}' isn't a subject"))?;
+    let title = form.get("title").unwrap_or_default();
+    if title.trim().is_empty() {
+        return Err("the set needs a title".to_string());
+    }
+    let mut cards = Vec::new();
+    for index in 0.. {
+        let (Ok(term), Ok(definition)) = (
+            form.get(&format!("term-{index}")),
+            form.get(&format!("definition-{index}")),
+        ) else {
+            break;
+        };
+        let is_cloze = !cloze::numbers(&term).is_empty();
+        match (term.trim().is_empty(), definition.trim().is_empty()) {
+            (true, true) => {}
+            (false, no_definition) if is_cloze || !no_definition => cards.push(Card {
+                id: Uuid::new_v4().to_string(),
+                term,
+                definition: definition.replace("\r\n", "\n"),
+            }),
+            _ => return Err(format!("card {} needs a term and a definition", index + 1)),
+        }
+    }
+    Ok(Set {
+        id: String::new(),
+        title: title.trim().to_string(),
+        description: form.get("description").unwrap_or_default(),
+        subject: subject.clone(),
+        folder: None,
+        remix_of: None,
+        algorithm: None,
+        cards,
+    })
+}
+
+/// The title in lowercase with its words joined by dashes, for use in an id.
+pub fn slug(title: &str) -> String {
+    let slug = title
+        .split(|character: char| !character.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+        .join("-");
+    if slug.is_empty() {
+        "set".to_string()
+    } else {
+        slug
+    }
+}
+
+/// `{subject}/{title as a slug}`, with a number on the end if that is taken. The
+/// subject can also be a folder's id, to put the set in that folder.
+pub async fn unique_id(
+    store: &dyn Store,
+    subject: &str,
+    title: &str,
+) -> Result<String, ResourceError> {
+    let slug = slug(title);
+    let mut id = format!("{subject}/{slug}");
+    for number in 2.. {
+        match store.get_set(&id).await {
+            Err(ResourceError::NotFound(_)) => break,
+            Err(error) => return Err(error),
+            Ok(_) => id = format!("{subject}/{slug}-{number}"),
+        }
+    }
+    Ok(id)
+}
+
+/// Tells htmx to navigate to `location` once the request finishes.
+pub fn redirect(location: &str) -> Result<Response<Full<Bytes>>, ResourceError> {
+    Ok(http::Response::builder()
+        .header("HX-Redirect", location)
+        .body(Full::new(Bytes::new()))
+        .unwrap())
+}
+
+/// The definition, which only a cloze card can leave empty, with the line breaks
+/// browsers send from a text area as plain new lines.
+fn definition(term: &str, definition: String) -> Result<String, ResourceError> {
+    let definition = definition.replace("\r\n", "\n");
+    if cloze::numbers(term).is_empty() {
+        non_empty(definition, "definition")
+    } else {
+        Ok(definition)
+    }
+}
+
+fn non_empty(value: String, field: &str) -> Result<String, ResourceError> {
+    if value.trim().is_empty() {
+        Err(ResourceError::Custom(format!("'{field}' can't be empty")))
+    } else {
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::fixtures::{algebra, maths};
+    use crate::store::MemoryStore;
+
+    #[tokio::test]
+    async fn remix_copies_the_set_and_opens_the_copy() {
+        let store = MemoryStore::new(vec![maths()], Vec::new(), [algebra()]);
+        let response = remix("maths/algebra", &store).await.unwrap();
+        assert_eq!(
+            response.headers()["HX-Redirect"],
+            "/edit-set/maths/algebra-remix"
+        );
+        let remixes = store.remixes("maths/algebra").await.unwrap();
+        assert_eq!(remixes.len(), 1);
+        assert_eq!(remixes[0].title, "algebra (remix)");
+        assert_eq!(remixes[0].cards[0].term, "x + x");
+        // the copy's cards are scheduled separately from the original's
+        assert_ne!(remixes[0].cards[0].id, "x-plus-x");
+        assert!(remix("maths/geometry", &store).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unique_id_numbers_titles_that_are_taken() {
+        let store = MemoryStore::new(vec![maths()], Vec::new(), [algebra()]);
+        assert_eq!(
+            unique_id(&store, "maths", "Algebra!").await.unwrap(),
+            "maths/algebra-2"
+        );
+        assert_eq!(
+            unique_id(&store, "maths", "Simultaneous equations")
+                .await
+                .unwrap(),
+            "maths/simultaneous-equations"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use crate::data::Rating;
+use std::collections::HashSet;
+
+/// Answers with at least this many words are also marked on the keywords they contain.
+const LONG_ANSWER_WORDS: usize = 6;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "caused", "e", "eg", "for", "from", "g", "in",
+    "into", "is", "it", "of", "on", "or", "that", "the", "their", "there", "this", "to", "was",
+    "what", "when", "where", "which", "with",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Segment {
+    /// In both the answer and the response.
+    Same(String),
+    /// In the answer, but left out of the response.
+    Missing(String),
+    /// In the response, but not the answer.
+    Extra(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grade {
+    /// From 0 (nothing right) to 1 (exactly right, ignoring case and punctuation).
+    pub score: f64,
+    pub rating: Rating,
+    pub diff: Vec<Segment>,
+    /// Keywords from a long answer that the response didn't mention.
+    pub missing_keywords: Vec<String>,
+}
+
+/// Lowercases, drops punctuation and collapses whitespace.
+pub fn normalise(text: &str) -> String {
+    text.chars()
+        .map(|character| {
+            if character.is_alphanumeric() {
+                character
+            } else {
+                ' '
+            }
+        })
+        .flat_map(char::to_lowercase)
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// 1 minus the edit distance as a fraction of the longer string's length.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let length = a.chars().count().max(b.chars().count());
+    if length == 0 {
+        return 1.0;
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let similarity = 1.0 - levenshtein(a, b) as f64 / length as f64;
+    similarity
+}
+
+/// A word-by-word diff between the expected answer and the response, from the
+/// longest common subsequence of their words.
+pub fn diff(expected: &str, given: &str) -> Vec<Segment> {
+    let expected = expected.split_whitespace().collect::<Vec<_>>();
+    let given = given.split_whitespace().collect::<Vec<_>>();
+    // lengths[i][j] is the LCS length of expected[i..] and given[j..]
+    let mut lengths = vec![vec![0_usize; given.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..given.len()).rev() {
+            lengths[i][j] = if words_match(expected[i], given[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut segments = Vec::<Segment>::new();
+    // consecutive words of the same kind are merged into one segment
+    let mut push = |segment: Segment| {
+        if let (Some(Segment::Same(text)), Segment::Same(word))
+        | (Some(Segment::Missing(text)), Segment::Missing(word))
+        | (Some(Segment::Extra(text)), Segment::Extra(word)) = (segments.last_mut(), &segment)
+        {
+            text.push(' ');
+            text.push_str(word);
+            return;
+        }
+        segments.push(segment);
+    };
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < given.len() {
+        if i < expected.len() && j < given.len() && words_match(expected[i], given[j]) {
+            push(Segment::Same(expected[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j < given.len() && (i == expected.len() || lengths[i][j + 1] >= lengths[i + 1][j])
+        {
+            push(Segment::Extra(given[j].to_string()));
+            j += 1;
+        } else {
+            push(Segment::Missing(expected[i].to_string()));
+            i += 1;
+        }
+    }
+    segments
+}
+
+/// Whether two words are the same once normalised, allowing a typo in longer words.
+fn words_match(a: &str, b: &str) -> bool {
+    let (a, b) = (normalise(a), normalise(b));
+    a == b || (a.chars().count() >= 5 && levenshtein(&a, &b) <= 1)
+}
+
+/// The words in an answer worth checking for, without stopwords or repeats.
+pub fn keywords(answer: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    normalise(answer)
+        .split(' ')
+        .filter(|word| word.chars().count() > 2 && !STOPWORDS.contains(word))
+        .filter(|word| seen.insert(word.to_string()))
+        .map(ToString::to_string)
+        .collect()
+}
+
+pub fn rating(score: f64) -> Rating {
+    if score >= 0.98 {
+        Rating::Perfect
+    } else if score >= 0.85 {
+        Rating::Good
+    } else if score >= 0.6 {
+        Rating::Ok
+    } else if score >= 0.3 {
+        Rating::Bad
+    } else {
+        Rating::Terrible
+    }
+}
+
+/// Marks a typed response against the expected answer. Short answers are marked on
+/// how close the whole response is; long answers can instead earn partial credit
+/// for each of their keywords the response mentions.
+pub fn grade(expected: &str, given: &str) -> Grade {
+    let normalised = normalise(expected);
+    let response = normalise(given);
+    let mut score = similarity(&normalised, &response);
+    let mut missing_keywords = Vec::new();
+    if normalised.split(' ').count() >= LONG_ANSWER_WORDS {
+        let keywords = keywords(expected);
+        let given_words = response.split(' ').collect::<Vec<_>>();
+        let (found, missing): (Vec<_>, Vec<_>) = keywords
+            .into_iter()
+            .partition(|keyword| given_words.iter().any(|word| words_match(keyword, word)));
+        if !(found.is_empty() && missing.is_empty()) {
+            #[allow(clippy::cast_precision_loss)]
+            let keyword_score = found.len() as f64 / (found.len() + missing.len()) as f64;
+            score = score.max(keyword_score);
+        }
+        missing_keywords = missing;
+    }
+    Grade {
+        score,
+        rating: rating(score),
+        diff: diff(expected.trim(), given.trim()),
+        missing_keywords,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalise_ignores_case_punctuation_and_spacing() {
+        assert_eq!(normalise("  The Earth's  CORE!"), "the earth s core");
+    }
+
+    #[test]
+    fn accented_capitals_are_lowercased() {
+        assert_eq!(normalise("Árbol"), "árbol");
+        assert_eq!(grade("Él", "él").rating, Rating::Perfect);
+        assert_eq!(grade("Árbol", "árbol").rating, Rating::Perfect);
+        // short words are counted in letters, not bytes
+        assert_eq!(keywords("para mí el árbol"), ["para", "árbol"]);
+    }
+
+    #[test]
+    fn levenshtein_counts_edits() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert!((similarity("", "") - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn exact_answers_are_perfect() {
+        let grade = grade("Paris", "paris");
+        assert_eq!(grade.rating, Rating::Perfect);
+        assert_eq!(grade.diff, [Segment::Same("Paris".to_string())]);
+    }
+
+    #[test]
+    fn typos_are_good() {
+        let grade = grade("photosynthesis", "photosynthesys");
+        assert_eq!(grade.rating, Rating::Good);
+        // a single typo in a long word still counts as the same word
+        assert_eq!(grade.diff, [Segment::Same("photosynthesis".to_string())]);
+    }
+
+    #[test]
+    fn wrong_answers_are_terrible() {
+        assert_eq!(grade("Paris", "London").rating, Rating::Terrible);
+    }
+
+    #[test]
+    fn long_answers_earn_credit_for_keywords() {
+        let grade = grade(
+            "the movement of water across a partially permeable membrane",
+            "water moves across a membrane",
+        );
+        assert!((grade.score - 0.5).abs() < f64::EPSILON);
+        assert_eq!(grade.rating, Rating::Bad);
+        assert_eq!(
+            grade.missing_keywords,
+            ["movement", "partially", "permeable"]
+        );
+    }
+
+    #[test]
+    fn keywords_skip_stopwords_and_repeats() {
+        assert_eq!(
+            keywords("The cause of the cause is the effect"),
+            ["cause", "effect"]
+        );
+    }
+
+    #[test]
+    fn diff_merges_runs_of_words() {
+        assert_eq!(
+            diff("the quick brown fox", "the quack fox jumps"),
+            [
+                Segment::Same("the quick".to_string()),
+                Segment::Missing("brown".to_string()),
+                Segment::Same("fox".to_string()),
+                Segment::Extra("jumps".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! What the importers that make many sets at once (Anki packages and Markdown
+//! notes) share: putting sets inside folders, and reporting what was made. Every
+//! importer also makes sure its cards don't take ids already in the store.
+use crate::data::{Card, Folder, ResourceError, Set, Subject};
+use crate::editor;
+use crate::store::Store;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use uuid::Uuid;
+
+/// The ids of every card in the store.
+pub async fn card_ids(store: &dyn Store) -> Result<HashSet<String>, ResourceError> {
+    let mut ids = HashSet::new();
+    for subject in store.subjects().await? {
+        for set in store.sets(&subject.id).await? {
+            ids.extend(set.cards.into_iter().map(|card| card.id));
+        }
+    }
+    Ok(ids)
+}
+
+/// Gives new ids to any cards whose ids are in `taken`, and adds their ids to it.
+/// Without this, importing the same file twice (or a set exported from here) would
+/// make cards that share their ids, and review state, with the first copy.
+pub fn fresh_card_ids(cards: &mut [Card], taken: &mut HashSet<String>) {
+    for card in cards {
+        while !taken.insert(card.id.clone()) {
+            card.id = Uuid::new_v4().to_string();
+        }
+    }
+}
+
+/// What an import created and what it had to leave out.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    /// The id, title and number of cards of each set created.
+    pub sets: Vec<(String, String, usize)>,
+    pub folders: Vec<String>,
+    /// How many media files were stored.
+    pub media: usize,
+    /// How many things were left out, by why they were.
+    pub skipped: BTreeMap<String, usize>,
+    /// Every card id in the store, loaded when the first set is saved.
+    card_ids: Option<HashSet<String>>,
+}
+
+impl Report {
+    pub fn skip(&mut self, reason: impl Into<String>) {
+        *self.skipped.entry(reason.into()).or_default() += 1;
+    }
+
+    /// Saves the cards as a set called `title`, inside the folders named by
+    /// `folders` (outermost first), creating any folders that aren't there yet.
+    /// Cards whose ids are already taken get new ones.
+    pub async fn save_set(
+        &mut self,
+        store: &dyn Store,
+        subject: &Subject,
+        folders: &[&str],
+        title: &str,
+        description: String,
+        mut cards: Vec<Card>,
+    ) -> Result<(), ResourceError> {
+        if self.card_ids.is_none() {
+            self.card_ids = Some(card_ids(store).await?);
+        }
+        fresh_card_ids(&mut cards, self.card_ids.get_or_insert_with(HashSet::new));
+        let mut folder = None::<String>;
+        for name in folders {
+            let id = format!(
+                "{}/{}",
+                folder.as_deref().unwrap_or(&subject.id),
+                editor::slug(name)
+            );
+            if !self.folders.contains(&id) {
+                store
+                    .save_folder(&Folder {
+                        id: id.clone(),
+                        title: (*name).to_string(),
+                        subject: subject.id.clone(),
+                        parent: folder.clone(),
+                    })
+                    .await?;
+                self.folders.push(id.clone());
+            }
+            folder = Some(id);
+        }
+        let set = Set {
+            id: editor::unique_id(store, folder.as_deref().unwrap_or(&subject.id), title).await?,
+            title: title.to_string(),
+            description,
+            subject: subject.clone(),
+            folder,
+            remix_of: None,
+            algorithm: None,
+            cards,
+        };
+        store.save_set(&set).await?;
+        self.sets.push((set.id, set.title, set.cards.len()));
+        Ok(())
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (id, _, cards) in &self.sets {
+            writeln!(f, "created set {id} ({cards} cards)")?;
+        }
+        for id in &self.folders {
+            writeln!(f, "created folder {id}")?;
+        }
+        for (reason, count) in &self.skipped {
+            writeln!(f, "skipped {count} {reason}")?;
+        }
+        writeln!(f, "stored {} media files", self.media)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::fixtures::geography;
+    use crate::store::MemoryStore;
+
+    fn cards() -> Vec<Card> {
+        vec![Card {
+            id: "shield".to_string(),
+            term: "shield volcano".to_string(),
+            definition: "gentle slopes of runny lava".to_string(),
+        }]
+    }
+
+    #[tokio::test]
+    async fn save_set_puts_sets_in_folders_made_once() {
+        let subject = geography();
+        let store = MemoryStore::new(vec![subject.clone()], Vec::new(), Vec::new());
+        let mut report = Report::default();
+        report
+            .save_set(
+                &store,
+                &subject,
+                &["Hazards", "Volcanoes"],
+                "Types",
+                String::new(),
+                cards(),
+            )
+            .await
+            .unwrap();
+        report
+            .save_set(
+                &store,
+                &subject,
+                &["Hazards"],
+                "Types",
+                String::new(),
+                cards(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            report.folders,
+            ["geography/hazards", "geography/hazards/volcanoes"]
+        );
+        let folders = store.folders("geography").await.unwrap();
+        assert_eq!(folders.len(), 2);
+        assert_eq!(folders[1].parent.as_deref(), Some("geography/hazards"));
+        let ids = report
+            .sets
+            .iter()
+            .map(|(id, _, _)| id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            [
+                "geography/hazards/volcanoes/types",
+                "geography/hazards/types"
+            ]
+        );
+        let set = store
+            .get_set("geography/hazards/volcanoes/types")
+            .await
+            .unwrap();
+        assert_eq!(set.folder.as_deref(), Some("geography/hazards/volcanoes"));
+        assert_eq!(set.cards, cards());
+        // importing the same cards again makes copies rather than sharing ids
+        let again = store.get_set("geography/hazards/types").await.unwrap();
+        assert_eq!(again.cards[0].term, "shield volcano");
+        assert_ne!(again.cards[0].id, "shield");
+    }
+}
@@ -0,0 +1,24 @@
+#![warn(clippy::pedantic, clippy::nursery, clippy::todo)]
+pub mod anki;
+pub mod cloze;
+pub mod components;
+pub mod csv;
+pub mod data;
+pub mod database;
+pub mod editor;
+pub mod grading;
+pub mod import;
+pub mod markdown;
+pub mod matching;
+pub mod media;
+pub mod migrate;
+pub mod paste;
+pub mod quiz;
+pub mod random;
+pub mod scheduler;
+pub mod session;
+pub mod settings;
+pub mod store;
+pub mod study;
+pub mod test_paper;
+pub mod write;
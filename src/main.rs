@@ -1,6 +1,15 @@
 // htmx-swapping
 #![warn(clippy::pedantic, clippy::nursery, clippy::todo)]
-use data::{Query, ResourceError, Set, Subject};
+use flopcards::data::{Direction, Folder, Query, ResourceError};
+use flopcards::database::Database;
+use flopcards::scheduler::{Algorithm, Progress};
+use flopcards::session::{Sessions, CRAM_STREAK};
+use flopcards::store::{FileStore, Store};
+use flopcards::study::{self, Scope};
+use flopcards::{
+    anki, components, csv, editor, markdown, matching, media, paste, quiz, settings, test_paper,
+    write,
+};
 use html_builder::prelude::*;
 use http::Method;
 use http_body_util::Full;
@@ -9,73 +18,169 @@ use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Request, Response};
 use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
 use std::fs;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::net::TcpListener;
-mod components;
-mod data;
 
-fn index(request: Request<hyper::body::Incoming>) -> Result<Html, ResourceError> {
-    let subjects = Subject::fetch_all();
+async fn index(
+    request: Request<hyper::body::Incoming>,
+    store: &dyn Store,
+) -> Result<Html, ResourceError> {
+    let subjects = store.subjects().await?;
     let query = Query::from_request(&request);
-    let sets = if let Ok(subject) = query.get("subject") {
-        Set::fetch_all(&subject)?
+    let set_list = if query.get("subject").is_ok() {
+        set_list(&query, store).await?
     } else {
-        Vec::new()
+        components::set_list(None, &[], &[], &[])
     };
-    Ok(html("en")
-        .child(
-            head()
-                .template()
-                .style(include_str!("./output.css"))
-                .title("flopcards - home")
-                .raw_text("<script src='assets/htmx.min.js'></script>"),
-        )
-        .child(
-            body()
-                .class("p-8 grid place-items-center items-start gap-8 bg-neutral")
-                .child(h1("flopcards"))
-                .child(components::subject_menu(&subjects))
-                .child(components::set_list(&sets))
-                .child(components::loading_animation())
-                .script(include_str!("../script.js")),
-        ))
+    Ok(components::page(
+        "flopcards - home",
+        body()
+            .child(h1("flopcards"))
+            .child(components::session_list(&Sessions::load()?.unfinished()))
+            .child(components::subject_menu(&subjects))
+            .child(set_list)
+            .child(a("/settings").class("underline").text("settings"))
+            .child(components::loading_animation())
+            .child(components::create_menu(None)),
+    ))
 }
 
-fn sets_view(request: Request<hyper::body::Incoming>) -> Result<Section, ResourceError> {
+async fn sets_view(
+    request: Request<hyper::body::Incoming>,
+    store: &dyn Store,
+) -> Result<Section, ResourceError> {
     let request = &request;
     let query = Query::from_request(request);
+    set_list(&query, store).await
+}
+
+/// The folders and sets directly inside `?folder=`, or at the top of `?subject=`
+/// if no folder is given.
+async fn set_list(query: &Query, store: &dyn Store) -> Result<Section, ResourceError> {
     let subject = query.get("subject")?;
-    Ok(components::set_list(&Set::fetch_all(&subject)?))
+    let folder = query.get("folder").ok();
+    let folders = store.folders(&subject).await?;
+    let breadcrumbs = folder
+        .as_deref()
+        .map(|folder| Folder::breadcrumbs(&folders, folder))
+        .unwrap_or_default();
+    let folders = folders
+        .into_iter()
+        .filter(|child| child.parent == folder)
+        .collect::<Vec<_>>();
+    let sets = store
+        .sets(&subject)
+        .await?
+        .into_iter()
+        .filter(|set| set.folder == folder)
+        .collect::<Vec<_>>();
+    Ok(components::set_list(
+        Some(&subject),
+        &breadcrumbs,
+        &folders,
+        &sets,
+    ))
 }
 
-fn set(path: &str) -> Result<Html, ResourceError> {
-    let set = Set::get(path).unwrap_or_else(|_| todo!("404"));
-    Ok(html("en")
-        .child(
-            head()
-                .template()
-                .style(include_str!("./output.css"))
-                .title(format!("{} - flopcards", set.title))
-                .raw_text("<script src='assets/htmx.min.js'></script>"),
-        )
-        .child(
-            body()
-                .class("p-8 grid place-items-center items-start gap-8 bg-neutral")
-                .child(h1("Study"))
-                .child(components::flashcard_stack(set.cards))
-                .script(include_str!("../script.js")),
-        ))
+/// `?direction=`, or term first if it isn't given.
+fn direction(query: &Query) -> Result<Direction, ResourceError> {
+    query
+        .get("direction")
+        .map_or(Ok(Direction::default()), |direction| direction.parse())
+}
+
+/// The streak each card needs from `?cram=`, if the session is a cram. An empty
+/// `?cram=` uses the default streak.
+fn cram(query: &Query) -> Result<Option<u32>, ResourceError> {
+    match query.get("cram") {
+        Err(_) => Ok(None),
+        Ok(streak) if streak.is_empty() => Ok(Some(CRAM_STREAK)),
+        Ok(_) => query.parse("cram").map(Some),
+    }
+}
+
+async fn set<T>(
+    request: &Request<T>,
+    path: &str,
+    store: &dyn Store,
+) -> Result<Html, ResourceError> {
+    let set = store.get_set(path).await?;
+    let direction = direction(&Query::from_request(request))?;
+    let progress = Progress::load()?;
+    let session = study::start(&Scope::Sets(vec![set.id.clone()]), direction, None, store).await?;
+    Ok(components::page(
+        format!("{} - flopcards", set.title),
+        body()
+            .child(h1("Study"))
+            .child(
+                nav()
+                    .class("flex gap-4")
+                    .child(
+                        a(format!("/sets/{}/write", set.id))
+                            .class("underline")
+                            .text("write"),
+                    )
+                    .child(
+                        a(format!("/sets/{}/quiz", set.id))
+                            .class("underline")
+                            .text("quiz"),
+                    )
+                    .child(
+                        a(format!("/sets/{}/match", set.id))
+                            .class("underline")
+                            .text("match"),
+                    )
+                    .child(
+                        a(format!("/study?sets={}&cram=", set.id))
+                            .class("underline")
+                            .text("cram"),
+                    )
+                    .child(
+                        a(format!("/sets/{}/test", set.id))
+                            .class("underline")
+                            .text("test"),
+                    )
+                    .child(
+                        a(format!("/sets/{}/remixes", set.id))
+                            .class("underline")
+                            .text("remixes"),
+                    )
+                    .child(
+                        a(format!("/sets/{}/export.apkg", set.id))
+                            .class("underline")
+                            .text("export to Anki"),
+                    )
+                    .child(
+                        a(format!("/sets/{}/export.csv", set.id))
+                            .class("underline")
+                            .text("export CSV"),
+                    ),
+            )
+            .child(components::direction_menu(&set.id, direction))
+            .children(
+                (set.algorithm.unwrap_or(progress.algorithm) == Algorithm::Leitner).then(|| {
+                    let (boxes, unboxed) = progress.leitner_boxes(&set, direction);
+                    components::leitner_boxes(&boxes, unboxed)
+                }),
+            )
+            .child(study::study_card(session, store).await?)
+            .child(components::create_menu(Some(&set))),
+    ))
 }
 
 async fn router(
     request: Request<hyper::body::Incoming>,
+    store: Arc<dyn Store>,
 ) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let store = store.as_ref();
     let path = request.uri().path();
     match *request.method() {
         Method::GET => {
             if path == "/" {
-                index(request)?.response_ok()
+                index(request, store).await?.response_ok()
             } else if path == "/favicon.ico" {
                 let path = format!("/{}/assets/favicon.ico", env!("CARGO_MANIFEST_DIR"));
                 let bytes = fs::read(path)
@@ -102,13 +207,172 @@ async fn router(
                     .body(Full::new(Bytes::from(bytes)))
                     .unwrap();
                 return Ok(response);
+            } else if let Some(name) = path.strip_prefix("/media/") {
+                let (bytes, content_type) = media::read(name)?;
+                let response = http::Response::builder()
+                    .header(http::header::CONTENT_TYPE, content_type)
+                    .body(Full::new(Bytes::from(bytes)))
+                    .unwrap();
+                return Ok(response);
+            } else if path == "/settings" {
+                settings::page()?.response_ok()
+            } else if path == "/import/anki" {
+                anki::page(store).await?.response_ok()
+            } else if path == "/import/text" {
+                paste::page(store).await?.response_ok()
+            } else if path == "/import/csv" {
+                csv::page(store).await?.response_ok()
+            } else if path == "/import/markdown" {
+                markdown::page(store).await?.response_ok()
+            } else if path == "/study" {
+                let query = Query::from_request(&request);
+                let scope = request
+                    .uri()
+                    .query()
+                    .and_then(Scope::from_query)
+                    .ok_or_else(|| {
+                        ResourceError::Custom("pick some sets, a subject or a folder".to_string())
+                    })?;
+                let session =
+                    study::start(&scope, direction(&query)?, cram(&query)?, store).await?;
+                study::page(session, store).await?.response_ok()
+            } else if let Some(id) = path.strip_prefix("/session/") {
+                let session = Sessions::load()?.get(id)?.clone();
+                study::page(session, store).await?.response_ok()
             } else if let Some(path) = path.strip_prefix("/view/") {
                 match path {
-                    "sets" => sets_view(request)?.response_ok(),
+                    "sets" => sets_view(request, store).await?.response_ok(),
+                    "write" => {
+                        let query = Query::from_request(&request);
+                        write::prompt(&query, store).await?.response_ok()
+                    }
+                    "quiz" => {
+                        let query = Query::from_request(&request);
+                        quiz::prompt(&query, store).await?.response_ok()
+                    }
+                    "card-row" => {
+                        let index = Query::from_request(&request).parse("index")?;
+                        components::new_card_rows(index, 1).response_ok()
+                    }
                     _ => Err(ResourceError::NotFound(format!("/view/{path}"))),
                 }
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/export.apkg"))
+            {
+                anki::export_set(id, store).await
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/export.csv"))
+            {
+                csv::export(id, ',', store).await
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/export.tsv"))
+            {
+                csv::export(id, '\t', store).await
+            } else if let Some(id) = path
+                .strip_prefix("/subjects/")
+                .and_then(|path| path.strip_suffix("/export.apkg"))
+            {
+                anki::export_subject(id, store).await
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/remixes"))
+            {
+                editor::remixes_page(id, store).await?.response_ok()
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/write"))
+            {
+                let query = Query::from_request(&request);
+                write::page(id, &query, store).await?.response_ok()
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/quiz"))
+            {
+                let query = Query::from_request(&request);
+                quiz::page(id, &query, store).await?.response_ok()
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/match"))
+            {
+                matching::page(id, store).await?.response_ok()
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/test"))
+            {
+                let query = Query::from_request(&request);
+                test_paper::page(id, &query, store).await?.response_ok()
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/test/answers"))
+            {
+                let query = Query::from_request(&request);
+                test_paper::answers_page(id, &query, store)
+                    .await?
+                    .response_ok()
             } else if let Some(path) = path.strip_prefix("/sets/") {
-                set(path)?.response_ok()
+                set(&request, path, store).await?.response_ok()
+            } else if let Some(id) = path.strip_prefix("/edit-set/") {
+                editor::page(id, store).await?.response_ok()
+            } else if path == "/create-set" {
+                editor::create_page(store).await?.response_ok()
+            } else {
+                Err(ResourceError::NotFound(path.to_string()))
+            }
+        }
+        Method::POST => {
+            if path == "/create-set" {
+                editor::create(request, store).await
+            } else if let Some(path) = path.strip_prefix("/settings/") {
+                settings::update(request, path).await
+            } else if path == "/import/anki" {
+                anki::upload(request, store).await?.response_ok()
+            } else if path == "/import/text" {
+                paste::create(request, store).await
+            } else if path == "/import/csv" {
+                csv::upload(request, store).await?.response_ok()
+            } else if path == "/import/markdown" {
+                markdown::upload(request, store).await?.response_ok()
+            } else if path == "/view/csv-preview" {
+                csv::preview(request).await?.response_ok()
+            } else if path == "/view/paste-preview" {
+                paste::preview(request).await?.response_ok()
+            } else if let Some(path) = path.strip_prefix("/match/") {
+                matching::pick(path)?.response_ok()
+            } else if let Some(id) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/remix"))
+            {
+                editor::remix(id, store).await
+            } else if let Some(path) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/write"))
+            {
+                let path = path.to_string();
+                write::answer(request, &path, store).await?.response_ok()
+            } else if let Some(path) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/quiz"))
+            {
+                let query = Query::from_request(&request);
+                quiz::answer(&query, path, store).await?.response_ok()
+            } else if let Some(path) = path
+                .strip_prefix("/sets/")
+                .and_then(|path| path.strip_suffix("/review"))
+            {
+                let query = Query::from_request(&request);
+                study::review(path, &query, store).await?.response_ok()
+            } else if let Some(id) = path
+                .strip_prefix("/session/")
+                .and_then(|path| path.strip_suffix("/answer"))
+            {
+                let query = Query::from_request(&request);
+                study::answer(id, &query, store).await?.response_ok()
+            } else if let Some(path) = path.strip_prefix("/edit-set/") {
+                let path = path.to_string();
+                editor::update(request, &path, store).await
             } else {
                 Err(ResourceError::NotFound(path.to_string()))
             }
@@ -117,7 +381,22 @@ async fn router(
     }
 }
 
-struct App;
+/// Routes the request, answering with the error's status code if that fails.
+async fn respond(
+    request: Request<hyper::body::Incoming>,
+    store: Arc<dyn Store>,
+) -> Result<Response<Full<Bytes>>, Infallible> {
+    Ok(router(request, store).await.unwrap_or_else(|error| {
+        if error.status().is_server_error() {
+            eprintln!("Error handling request: {error:?}");
+        }
+        error.response()
+    }))
+}
+
+struct App {
+    store: Arc<dyn Store>,
+}
 
 impl App {
     async fn run(self, addr: std::net::SocketAddr) -> Result<(), shuttle_runtime::Error> {
@@ -125,9 +404,10 @@ impl App {
         loop {
             let (stream, _) = listener.accept().await?;
             let io = TokioIo::new(stream);
+            let store = self.store.clone();
             tokio::task::spawn(async move {
                 if let Err(err) = http1::Builder::new()
-                    .serve_connection(io, service_fn(|request| router(request)))
+                    .serve_connection(io, service_fn(|request| respond(request, store.clone())))
                     .await
                 {
                     eprintln!("Error serving connection: {err:?}");
@@ -158,5 +438,21 @@ impl shuttle_runtime::Service for App {
 async fn main(
     #[shuttle_runtime::Secrets] secrets: shuttle_runtime::SecretStore,
 ) -> Result<App, shuttle_runtime::Error> {
-    Ok(App)
+    let store: Arc<dyn Store> =
+        if let (Some(url), Some(token)) = (secrets.get("TURSO_URL"), secrets.get("TURSO_TOKEN")) {
+            Arc::new(
+                Database::open_remote(&url, &token)
+                    .await
+                    .map_err(|err| shuttle_runtime::Error::Custom(err.into()))?,
+            )
+        } else if let Some(path) = secrets.get("DATABASE_PATH") {
+            Arc::new(
+                Database::open_local(&path)
+                    .await
+                    .map_err(|err| shuttle_runtime::Error::Custom(err.into()))?,
+            )
+        } else {
+            Arc::new(FileStore::new("./flashcards"))
+        };
+    Ok(App { store })
 }
@@ -0,0 +1,315 @@
+//! `/import/markdown`: making sets from Markdown notes. Cards are read from
+//! definition lists (a term, then `: definition` on the next line), from
+//! `**term** — definition` lines and from `term :: definition` lines; everything
+//! else is left alone. Each heading with cards directly under it becomes a set,
+//! inside folders for the headings above it.
+use crate::components;
+use crate::data::{body_to_string, Card, Query, ResourceError};
+use crate::import::Report;
+use crate::store::Store;
+use html_builder::prelude::*;
+use hyper::Request;
+use uuid::Uuid;
+
+/// The cards under a heading, with the headings it is inside (outermost first).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Section {
+    pub headings: Vec<String>,
+    pub cards: Vec<Card>,
+}
+
+/// The heading's level and text, if the line is one.
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line
+        .chars()
+        .take_while(|character| *character == '#')
+        .count();
+    let text = line[level..].strip_prefix(' ')?;
+    (1..=6)
+        .contains(&level)
+        .then(|| (level, text.trim().trim_end_matches('#').trim()))
+}
+
+/// The line without a list marker such as `- `, `* ` or `1. ` in front.
+fn without_list_marker(line: &str) -> &str {
+    let line = line.trim_start();
+    if let Some(rest) = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|marker| line.strip_prefix(marker))
+    {
+        return rest;
+    }
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    match line[digits..].strip_prefix(". ") {
+        Some(rest) if digits > 0 => rest,
+        _ => line,
+    }
+}
+
+/// The term and definition on a `**term** — definition` or `term :: definition`
+/// line. The definition is empty if the line has nothing after the separator.
+fn card_line(line: &str) -> Option<(&str, &str)> {
+    let line = without_list_marker(line).trim();
+    if let Some((term, rest)) = line
+        .strip_prefix("**")
+        .and_then(|line| line.split_once("**"))
+    {
+        let rest = rest.trim_start();
+        let definition = ["—", "–", "-", ":"]
+            .iter()
+            .find_map(|dash| rest.strip_prefix(dash))?;
+        return Some((term.trim(), definition.trim()));
+    }
+    // spaces around the `::` keep it apart from the ones in `{{c1::cloze}}` markers
+    line.split_once(" :: ")
+        .or_else(|| line.strip_suffix(" ::").map(|term| (term, "")))
+        .map(|(term, definition)| (term.trim(), definition.trim()))
+}
+
+fn card(term: &str, definition: &str) -> Card {
+    Card {
+        id: Uuid::new_v4().to_string(),
+        term: term.to_string(),
+        definition: definition.to_string(),
+    }
+}
+
+/// The sections of the notes that have cards in them, and how many lines looked
+/// like cards but had no definition.
+pub fn parse(text: &str) -> (Vec<Section>, usize) {
+    let mut sections = Vec::new();
+    let mut section = Section::default();
+    let mut headings = Vec::<(usize, String)>::new();
+    let mut missing = 0;
+    // the last line of text, which a `: definition` line makes a term
+    let mut term = None::<String>;
+    // whether the last card came from a definition list, so indented lines continue it
+    let mut in_definition = false;
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if line.trim().is_empty() {
+            // a blank line can come between a term and its definition, but not within one
+            in_definition = false;
+            continue;
+        }
+        if let Some((level, text)) = heading(line) {
+            while headings.last().is_some_and(|(above, _)| *above >= level) {
+                headings.pop();
+            }
+            headings.push((level, text.to_string()));
+            let next = Section {
+                headings: headings.iter().map(|(_, text)| text.clone()).collect(),
+                cards: Vec::new(),
+            };
+            sections.push(std::mem::replace(&mut section, next));
+            (term, in_definition) = (None, false);
+            continue;
+        }
+        if let Some(definition) = line
+            .strip_prefix(':')
+            .filter(|_| term.is_some() || in_definition)
+        {
+            let definition = definition.trim();
+            match (term.take(), section.cards.last_mut()) {
+                (Some(term), _) => section.cards.push(card(&term, definition)),
+                // another definition of the same term
+                (None, Some(last)) => {
+                    last.definition.push('\n');
+                    last.definition.push_str(definition);
+                }
+                (None, None) => {}
+            }
+            in_definition = true;
+            continue;
+        }
+        if in_definition && (line.starts_with("    ") || line.starts_with('\t')) {
+            if let Some(last) = section.cards.last_mut() {
+                last.definition.push('\n');
+                last.definition.push_str(line.trim());
+            }
+            continue;
+        }
+        in_definition = false;
+        term = None;
+        match card_line(line) {
+            Some((front, "")) if !front.is_empty() => missing += 1,
+            Some((front, definition)) if !front.is_empty() => {
+                section.cards.push(card(front, definition));
+            }
+            _ => term = Some(line.trim().to_string()),
+        }
+    }
+    sections.push(section);
+    sections.retain(|section| !section.cards.is_empty());
+    (sections, missing)
+}
+
+/// `/import/markdown`.
+pub async fn page(store: &dyn Store) -> Result<Html, ResourceError> {
+    let subjects = store.subjects().await?;
+    Ok(components::page(
+        "import notes - flopcards",
+        body()
+            .child(h1("Import notes"))
+            .child(p(
+                "upload Markdown notes: definition lists, **term** — definition lines and \
+                 term :: definition lines become cards, and each heading with cards under it \
+                 becomes a set, inside folders for the headings above it",
+            ))
+            .child(components::upload_form(
+                "markdown",
+                "/import/markdown",
+                &subjects,
+                None,
+            )),
+    ))
+}
+
+/// Handles `POST /import/markdown?subject=&filename=`, whose body is the notes,
+/// answering with the import report. Cards above the first heading go in a set
+/// named after the file.
+pub async fn upload(
+    request: Request<hyper::body::Incoming>,
+    store: &dyn Store,
+) -> Result<Div, ResourceError> {
+    let query = Query::from_request(&request);
+    let subject = query.get("subject")?;
+    let subject = store
+        .subjects()
+        .await?
+        .into_iter()
+        .find(|found| found.id == subject)
+        .ok_or_else(|| ResourceError::NotFound(format!("/?subject={subject}")))?;
+    let file = query.get("filename").unwrap_or_default();
+    let file = file
+        .rsplit_once('.')
+        .map_or(file.as_str(), |(stem, _)| stem);
+    let file = if file.is_empty() { "notes" } else { file };
+    let (sections, missing) = parse(&body_to_string(request).await?);
+    let mut report = Report::default();
+    for _ in 0..missing {
+        report.skip("cards with no definition");
+    }
+    for section in sections {
+        let (folders, title) = match section.headings.split_last() {
+            Some((title, folders)) => {
+                (folders.iter().map(String::as_str).collect(), title.as_str())
+            }
+            None => (Vec::new(), file),
+        };
+        report
+            .save_set(
+                store,
+                &subject,
+                &folders,
+                title,
+                format!("imported from {file}"),
+                section.cards,
+            )
+            .await?;
+    }
+    Ok(components::import_report(&report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each section's headings and its cards' terms and definitions.
+    fn cards(text: &str) -> (Vec<(Vec<String>, Vec<(String, String)>)>, usize) {
+        let (sections, missing) = parse(text);
+        let sections = sections
+            .into_iter()
+            .map(|section| {
+                let cards = section
+                    .cards
+                    .into_iter()
+                    .map(|card| (card.term, card.definition))
+                    .collect();
+                (section.headings, cards)
+            })
+            .collect();
+        (sections, missing)
+    }
+
+    fn strings<const N: usize>(strings: [&str; N]) -> Vec<String> {
+        strings.map(ToString::to_string).to_vec()
+    }
+
+    fn pair(term: &str, definition: &str) -> (String, String) {
+        (term.to_string(), definition.to_string())
+    }
+
+    #[test]
+    fn parse_reads_each_kind_of_card_under_its_headings() {
+        let notes = "\
+intro line
+**mitochondria** — powerhouse of the cell
+
+# Biology
+## Cells
+nucleus
+: holds the DNA
+: and the nucleolus
+ribosome
+: makes proteins
+    from amino acids
+- osmosis :: water moving across a membrane
+diffusion ::
+
+## Plants
+```
+fake :: card
+```
+chlorophyll :: green pigment
+### Empty
+just text
+";
+        let (sections, missing) = cards(notes);
+        assert_eq!(
+            sections,
+            [
+                (
+                    Vec::new(),
+                    vec![pair("mitochondria", "powerhouse of the cell")]
+                ),
+                (
+                    strings(["Biology", "Cells"]),
+                    vec![
+                        pair("nucleus", "holds the DNA\nand the nucleolus"),
+                        pair("ribosome", "makes proteins\nfrom amino acids"),
+                        pair("osmosis", "water moving across a membrane"),
+                    ]
+                ),
+                (
+                    strings(["Biology", "Plants"]),
+                    vec![pair("chlorophyll", "green pigment")]
+                ),
+            ]
+        );
+        assert_eq!(missing, 1);
+    }
+
+    #[test]
+    fn cloze_markers_are_not_separators() {
+        let (sections, missing) = cards("{{c1::Paris}} is the capital of France");
+        assert!(sections.is_empty());
+        assert_eq!(missing, 0);
+        let (sections, _) = cards("capital :: {{c1::Paris}}");
+        assert_eq!(sections[0].1, [pair("capital", "{{c1::Paris}}")]);
+    }
+
+    #[test]
+    fn headings_need_a_space_and_at_most_six_hashes() {
+        assert_eq!(heading("## Cells ##"), Some((2, "Cells")));
+        assert_eq!(heading("#hashtag"), None);
+        assert_eq!(heading("####### too deep"), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! `/sets/{id}/match`: terms and definitions shuffled into a grid to be paired up
+//! against the clock. Rounds live in memory; finished times are kept in
+//! `./match-times.json`.
+use crate::components;
+use crate::data::{Card, ResourceError, Side};
+use crate::random::Rng;
+use crate::scheduler::{self, DAY};
+use crate::store::Store;
+use html_builder::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// How many cards (so twice as many tiles) go into a round.
+pub const ROUND_CARDS: usize = 6;
+
+/// Added to the time for each wrong pair.
+pub const MISTAKE_PENALTY_MILLIS: i64 = 1000;
+
+/// How many times to show on the personal-best board.
+pub const BEST_TIMES: usize = 5;
+
+const TIMES_PATH: &str = "./match-times.json";
+
+/// Rounds in progress, by id.
+static ROUNDS: Mutex<BTreeMap<String, Round>> = Mutex::new(BTreeMap::new());
+static TIMES_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub card: String,
+    pub side: Side,
+    pub text: String,
+    pub matched: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Round {
+    pub id: String,
+    pub set: String,
+    pub tiles: Vec<Tile>,
+    /// The tile picked first, waiting for its pair.
+    pub selected: Option<usize>,
+    /// The last two tiles tried if they weren't a pair, to flash at the learner.
+    pub wrong: Option<(usize, usize)>,
+    pub mistakes: u32,
+    /// When the round started, in milliseconds.
+    pub started: i64,
+}
+
+impl Round {
+    /// Deals up to [`ROUND_CARDS`] cards from the set, shuffled by `seed`.
+    pub fn new(set: &str, cards: &[Card], seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let mut cards = cards.iter().collect::<Vec<_>>();
+        rng.shuffle(&mut cards);
+        let mut tiles = cards
+            .into_iter()
+            .take(ROUND_CARDS)
+            .flat_map(|card| {
+                [Side::Term, Side::Definition].map(|side| Tile {
+                    card: card.id.clone(),
+                    side,
+                    text: card.side(side).trim().to_string(),
+                    matched: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        rng.shuffle(&mut tiles);
+        Self {
+            id: Uuid::new_v4().to_string(),
+            set: set.to_string(),
+            tiles,
+            selected: None,
+            wrong: None,
+            mistakes: 0,
+            started: scheduler::now_millis(),
+        }
+    }
+
+    /// Selects a tile, checking it against the one already selected if there is one.
+    pub fn pick(&mut self, index: usize) -> Result<(), ResourceError> {
+        let tile = self
+            .tiles
+            .get(index)
+            .ok_or_else(|| ResourceError::Custom(format!("there is no tile {index}")))?;
+        self.wrong = None;
+        if tile.matched {
+            return Ok(());
+        }
+        match self.selected.take() {
+            None => self.selected = Some(index),
+            Some(selected) if selected == index => {}
+            Some(selected) => {
+                let other = &self.tiles[selected];
+                if other.card == tile.card && other.side != tile.side {
+                    self.tiles[selected].matched = true;
+                    self.tiles[index].matched = true;
+                } else {
+                    self.mistakes += 1;
+                    self.wrong = Some((selected, index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.tiles.iter().all(|tile| tile.matched)
+    }
+
+    /// How long the round took so far, with the penalty for mistakes.
+    pub fn elapsed(&self, now: i64) -> i64 {
+        now - self.started + i64::from(self.mistakes) * MISTAKE_PENALTY_MILLIS
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchTime {
+    /// Including the penalty for mistakes.
+    pub millis: i64,
+    pub mistakes: u32,
+    /// Unix timestamp of when the round was finished.
+    pub finished: i64,
+}
+
+/// Finished times, keyed by set id.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MatchTimes(pub HashMap<String, Vec<MatchTime>>);
+
+impl MatchTimes {
+    pub fn load() -> Result<Self, ResourceError> {
+        match fs::read_to_string(TIMES_PATH) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), ResourceError> {
+        fs::write(TIMES_PATH, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// The fastest [`BEST_TIMES`] times for the set, fastest first.
+    pub fn best(&self, set: &str) -> Vec<MatchTime> {
+        let mut times = self.0.get(set).cloned().unwrap_or_default();
+        times.sort_by_key(|time| (time.millis, time.finished));
+        times.truncate(BEST_TIMES);
+        times
+    }
+
+    pub fn record(set: &str, time: MatchTime) -> Result<Self, ResourceError> {
+        let _guard = TIMES_LOCK
+            .lock()
+            .map_err(|_| ResourceError::Custom("match times lock was poisoned".to_string()))?;
+        let mut times = Self::load()?;
+        times.0.entry(set.to_string()).or_default().push(time);
+        times.save()?;
+        Ok(times)
+    }
+}
+
+fn rounds() -> Result<std::sync::MutexGuard<'static, BTreeMap<String, Round>>, ResourceError> {
+    ROUNDS
+        .lock()
+        .map_err(|_| ResourceError::Custom("match rounds lock was poisoned".to_string()))
+}
+
+/// Starts a new round for the set, which needs at least two cards to match.
+pub async fn page(id: &str, store: &dyn Store) -> Result<Html, ResourceError> {
+    let set = store.get_set(id).await?;
+    let items = set.items();
+    if items.len() < 2 {
+        return Err(ResourceError::Custom(
+            "a set needs at least two cards to be matched".to_string(),
+        ));
+    }
+    let round = Round::new(&set.id, &items, scheduler::now_millis().unsigned_abs());
+    {
+        let mut rounds = rounds()?;
+        // rounds that were abandoned are dropped after a day
+        let cutoff = scheduler::now_millis() - DAY * 1000;
+        rounds.retain(|_, round| round.started > cutoff);
+        rounds.insert(round.id.clone(), round.clone());
+    }
+    Ok(components::page(
+        format!("{} - match - flopcards", set.title),
+        body().child(h1("Match")).child(components::match_round(
+            &round,
+            &MatchTimes::load()?.best(&set.id),
+        )),
+    ))
+}
+
+/// Handles `POST /match/{round}/{tile}`, answering with the round, or the finished
+/// time and the personal-best board once every pair has been found.
+pub fn pick(path: &str) -> Result<Div, ResourceError> {
+    let (id, index) = path
+        .split_once('/')
+        .ok_or_else(|| ResourceError::NotFound(format!("/match/{path}")))?;
+    let index = index
+        .parse()
+        .map_err(|_| ResourceError::NotFound(format!("/match/{path}")))?;
+    let round = {
+        let mut rounds = rounds()?;
+        let round = rounds
+            .get_mut(id)
+            .ok_or_else(|| ResourceError::NotFound(format!("/match/{path}")))?;
+        round.pick(index)?;
+        let round = round.clone();
+        if round.is_finished() {
+            rounds.remove(id);
+        }
+        round
+    };
+    if !round.is_finished() {
+        return Ok(components::match_round(
+            &round,
+            &MatchTimes::load()?.best(&round.set),
+        ));
+    }
+    let time = MatchTime {
+        millis: round.elapsed(scheduler::now_millis()),
+        mistakes: round.mistakes,
+        finished: scheduler::now(),
+    };
+    let times = MatchTimes::record(&round.set, time)?;
+    Ok(components::match_results(
+        &round.set,
+        &time,
+        &times.best(&round.set),
+    ))
+}
@@ -0,0 +1,77 @@
+//! Images and sounds that came with imported cards, kept in `./media` and served
+//! from `/media/{name}`. Cards refer to images as `![](/media/{name})` and to
+//! sounds as `[sound:{name}]`, as Anki does.
+use crate::data::ResourceError;
+use std::{fs, io::ErrorKind, path::PathBuf};
+
+const MEDIA_DIR: &str = "./media";
+
+/// `name` with anything that isn't safe in a file name or a URL replaced, so it
+/// can be written to disk and linked to as it is.
+pub fn file_name(name: &str) -> String {
+    let name = name
+        .chars()
+        .map(|character| match character {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => character,
+            _ => '-',
+        })
+        .collect::<String>();
+    name.trim_start_matches('.').to_string()
+}
+
+fn path(name: &str) -> Result<PathBuf, ResourceError> {
+    if name.is_empty() || file_name(name) != name {
+        return Err(ResourceError::NotFound(format!("/media/{name}")));
+    }
+    Ok(PathBuf::from(MEDIA_DIR).join(name))
+}
+
+/// Stores the file and returns the name it was stored under: its name prefixed with
+/// a hash of its contents, so that different files with the same name (such as
+/// `image.png` from two decks) don't replace each other.
+pub fn save(name: &str, bytes: &[u8]) -> Result<String, ResourceError> {
+    let hash = sha1_smol::Sha1::from(bytes).digest().to_string();
+    let name = format!("{}-{}", &hash[..16], file_name(name));
+    fs::create_dir_all(MEDIA_DIR)?;
+    fs::write(path(&name)?, bytes)?;
+    Ok(name)
+}
+
+/// Splits the text around its first `![alt](source)`.
+pub fn image(text: &str) -> Option<(&str, &str, &str, &str)> {
+    let start = text.find("![")?;
+    let (alt, rest) = text[start + 2..].split_once("](")?;
+    let (source, after) = rest.split_once(')')?;
+    Some((&text[..start], alt, source, after))
+}
+
+/// Splits the text around its first `[sound:name]`.
+pub fn sound(text: &str) -> Option<(&str, &str, &str)> {
+    let start = text.find("[sound:")?;
+    let (name, after) = text[start + "[sound:".len()..].split_once(']')?;
+    Some((&text[..start], name, after))
+}
+
+/// The file's contents and content type.
+pub fn read(name: &str) -> Result<(Vec<u8>, &'static str), ResourceError> {
+    let bytes = fs::read(path(name)?).map_err(|error| match error.kind() {
+        ErrorKind::NotFound => ResourceError::NotFound(format!("/media/{name}")),
+        _ => error.into(),
+    })?;
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    let content_type = match extension.as_deref() {
+        Some("svg") => "image/svg+xml; charset=utf-8",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("mp3") => "audio/mpeg",
+        Some("ogg") => "audio/ogg",
+        Some("wav") => "audio/wav",
+        Some("m4a") => "audio/mp4",
+        _ => "application/octet-stream",
+    };
+    Ok((bytes, content_type))
+}
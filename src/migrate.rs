@@ -0,0 +1,76 @@
+use crate::data::ResourceError;
+use crate::database::Database;
+use crate::store::{FileStore, Store};
+use std::{fmt::Display, path::PathBuf};
+
+/// What an import created, updated and found already in the database.
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub skipped: Vec<String>,
+}
+
+impl Report {
+    fn record(&mut self, item: String, created: bool) {
+        if created {
+            self.created.push(item);
+        } else {
+            self.skipped.push(item);
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for item in &self.created {
+            writeln!(f, "created {item}")?;
+        }
+        for item in &self.updated {
+            writeln!(f, "updated {item}")?;
+        }
+        for item in &self.skipped {
+            writeln!(f, "skipped {item}")?;
+        }
+        writeln!(
+            f,
+            "{} created, {} updated, {} skipped",
+            self.created.len(),
+            self.updated.len(),
+            self.skipped.len()
+        )
+    }
+}
+
+/// Copies every subject, folder and set under `root` into the database. Subjects
+/// that are already there get the colour from their `mod.toml`, and anything else
+/// whose id is already there is left alone, so running it twice is harmless.
+pub async fn migrate(
+    root: impl Into<PathBuf>,
+    database: &Database,
+) -> Result<Report, ResourceError> {
+    let files = FileStore::new(root);
+    let mut report = Report::default();
+    for subject in files.subjects().await? {
+        let item = format!("subject {}", subject.id);
+        match database.save_subject(&subject).await? {
+            Some(color) if color != subject.color => report
+                .updated
+                .push(format!("{item} colour from {color} to {}", subject.color)),
+            previous => report.record(item, previous.is_none()),
+        }
+        // sorted by id, so parents are inserted before the folders inside them
+        for folder in files.folders(&subject.id).await? {
+            let created = database.insert_folder(&folder).await?;
+            report.record(format!("folder {}", folder.id), created);
+        }
+        for set in files.sets(&subject.id).await? {
+            let created = !database.has_set(&set.id).await?;
+            if created {
+                database.save_set(&set).await?;
+            }
+            report.record(format!("set {}", set.id), created);
+        }
+    }
+    Ok(report)
+}
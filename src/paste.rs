@@ -0,0 +1,147 @@
+//! `/import/text`: making a set from pasted text, such as a Quizlet export, with
+//! the cards split apart by separators the learner picks and previewed as they
+//! type.
+use crate::components;
+use crate::data::{body_to_string, Card, Query, ResourceError, Set};
+use crate::editor;
+use crate::import;
+use crate::store::Store;
+use html_builder::prelude::*;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Request, Response};
+use uuid::Uuid;
+
+/// The cards in pasted text, and what was wrong with any bits that aren't cards.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pasted {
+    pub cards: Vec<Card>,
+    pub problems: Vec<String>,
+}
+
+/// Splits the text into cards at `card_separator`, and each card into its term and
+/// definition at the first `term_separator`. A card with no definition is only
+/// kept if it is a cloze card.
+pub fn parse(text: &str, term_separator: &str, card_separator: &str) -> Pasted {
+    let mut pasted = Pasted::default();
+    for (index, card) in text.split(card_separator).enumerate() {
+        let card = card.trim();
+        if card.is_empty() {
+            continue;
+        }
+        let (term, definition) = card
+            .split_once(term_separator)
+            .map_or((card, ""), |(term, definition)| {
+                (term.trim(), definition.trim())
+            });
+        let card = Card {
+            id: Uuid::new_v4().to_string(),
+            term: term.to_string(),
+            definition: definition.to_string(),
+        };
+        if card.term.is_empty() || (card.definition.is_empty() && !card.is_cloze()) {
+            pasted.problems.push(format!(
+                "card {} needs a term and a definition: \"{term}\"",
+                index + 1
+            ));
+        } else {
+            pasted.cards.push(card);
+        }
+    }
+    pasted
+}
+
+/// The separator picked for `name` (`term-separator` or `card-separator`), where
+/// a custom one can use `\t` and `\n` for tabs and new lines.
+fn separator(form: &Query, name: &str) -> Result<String, String> {
+    match form.get(name).as_deref() {
+        Ok("comma") => Ok(",".to_string()),
+        Ok("semicolon") => Ok(";".to_string()),
+        Ok("custom") => {
+            let custom = form
+                .get(&format!("{name}-custom"))
+                .unwrap_or_default()
+                .replace("\\t", "\t")
+                .replace("\\n", "\n");
+            if custom.is_empty() {
+                Err(format!("type the custom {}", name.replace('-', " ")))
+            } else {
+                Ok(custom)
+            }
+        }
+        Ok("newline") => Ok("\n".to_string()),
+        _ if name == "card-separator" => Ok("\n".to_string()),
+        _ => Ok("\t".to_string()),
+    }
+}
+
+fn parse_form(form: &Query) -> Result<Pasted, String> {
+    Ok(parse(
+        &form.get("text").unwrap_or_default(),
+        &separator(form, "term-separator")?,
+        &separator(form, "card-separator")?,
+    ))
+}
+
+/// `/import/text`.
+pub async fn page(store: &dyn Store) -> Result<Html, ResourceError> {
+    let subjects = store.subjects().await?;
+    Ok(components::page(
+        "paste cards - flopcards",
+        body()
+            .child(h1("Paste cards"))
+            .child(components::paste_form(&subjects)),
+    ))
+}
+
+/// Handles `POST /view/paste-preview` with the paste form, answering with the
+/// cards it would make.
+pub async fn preview(request: Request<hyper::body::Incoming>) -> Result<Div, ResourceError> {
+    let form = Query::from_str(&body_to_string(request).await?);
+    Ok(match parse_form(&form) {
+        Ok(pasted) => components::paste_preview(&pasted),
+        Err(message) => div().child(p(message).class("text-red-500")),
+    })
+}
+
+/// Makes a set from the paste form and sends the browser to its editor. Problems
+/// are shown above the import button instead.
+pub async fn create(
+    request: Request<hyper::body::Incoming>,
+    store: &dyn Store,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    let form = Query::from_str(&body_to_string(request).await?);
+    let subjects = store.subjects().await?;
+    let Some(subject) = form
+        .get("subject")
+        .ok()
+        .and_then(|subject| subjects.into_iter().find(|found| found.id == subject))
+    else {
+        return p("pick a subject").response_ok();
+    };
+    let title = form.get("title").unwrap_or_default();
+    if title.trim().is_empty() {
+        return p("the set needs a title").response_ok();
+    }
+    let pasted = match parse_form(&form) {
+        Ok(pasted) => pasted,
+        Err(message) => return p(message).response_ok(),
+    };
+    if pasted.cards.is_empty() {
+        return p("there aren't any cards to import").response_ok();
+    }
+    let mut cards = pasted.cards;
+    import::fresh_card_ids(&mut cards, &mut import::card_ids(store).await?);
+    let set = Set {
+        id: editor::unique_id(store, &subject.id, &title).await?,
+        title: title.trim().to_string(),
+        description: String::new(),
+        subject,
+        folder: None,
+        remix_of: None,
+        algorithm: None,
+        cards,
+    };
+    store.save_set(&set).await?;
+    editor::redirect(&format!("/edit-set/{}", set.id))
+}
@@ -0,0 +1,195 @@
+//! `/sets/{id}/quiz`: multiple-choice questions made from a set's cards, with the
+//! wrong answers taken from other cards that look like the right one.
+use crate::components;
+use crate::data::{Card, Query, Rating, ResourceError, Set, Side};
+use crate::grading::{keywords, normalise};
+use crate::random::Rng;
+use crate::scheduler::{self, next_card};
+use crate::store::Store;
+use crate::write::answer_side;
+use html_builder::prelude::*;
+use std::collections::HashSet;
+
+/// How many choices each question has, including the right one.
+pub const CHOICES: usize = 4;
+
+/// Sets with this many cards or fewer borrow wrong answers from the rest of their subject.
+const SMALL_SET: usize = 2 * CHOICES;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Question {
+    pub card: Card,
+    pub prompt: String,
+    pub choices: Vec<String>,
+    /// Index of the right answer in `choices`.
+    pub answer: usize,
+}
+
+/// How alike two answers look, from 0 to 1: half for being a similar length and
+/// half for the keywords they share.
+pub fn likeness(a: &str, b: &str) -> f64 {
+    let (a_length, b_length) = (a.chars().count(), b.chars().count());
+    #[allow(clippy::cast_precision_loss)]
+    let length = a_length.min(b_length) as f64 / a_length.max(b_length).max(1) as f64;
+    let a = keywords(a).into_iter().collect::<HashSet<_>>();
+    let b = keywords(b).into_iter().collect::<HashSet<_>>();
+    let union = a.union(&b).count();
+    #[allow(clippy::cast_precision_loss)]
+    let shared = if union == 0 {
+        0.0
+    } else {
+        a.intersection(&b).count() as f64 / union as f64
+    };
+    (length + shared) / 2.0
+}
+
+/// Picks `count` wrong answers from `candidates`. The most alike candidates are
+/// shortlisted and `rng` chooses among them, so repeated questions vary a little
+/// without the wrong answers becoming obvious.
+pub fn distractors<'a>(
+    answer: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+    count: usize,
+    rng: &mut Rng,
+) -> Vec<String> {
+    let normalised = normalise(answer);
+    let mut seen = HashSet::from([normalised.clone()]);
+    let mut candidates = candidates
+        .into_iter()
+        .map(str::trim)
+        .filter(|candidate| !candidate.is_empty() && seen.insert(normalise(candidate)))
+        .map(|candidate| (likeness(answer, candidate), candidate))
+        .collect::<Vec<_>>();
+    candidates.sort_by(|(a_score, a), (b_score, b)| b_score.total_cmp(a_score).then(a.cmp(b)));
+    candidates.truncate(count * 2);
+    rng.shuffle(&mut candidates);
+    candidates
+        .into_iter()
+        .take(count)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+/// Asks for the `answer` side of `card`, with wrong answers taken from `pool`. The
+/// same card, pool and seed always make the same question.
+pub fn question(card: &Card, pool: &[Card], answer: Side, seed: u64) -> Question {
+    let mut rng = Rng::with_key(seed, &card.id);
+    let right = card.side(answer).trim().to_string();
+    let mut choices = distractors(
+        &right,
+        pool.iter()
+            .filter(|other| other.id != card.id)
+            .map(|other| other.side(answer)),
+        CHOICES - 1,
+        &mut rng,
+    );
+    let index = rng.below(choices.len() + 1);
+    choices.insert(index, right);
+    Question {
+        card: card.clone(),
+        prompt: card.side(answer.other()).trim().to_string(),
+        choices,
+        answer: index,
+    }
+}
+
+/// The cards wrong answers can come from: the set itself, plus the rest of its
+/// subject if the set is small.
+async fn pool(set: &Set, store: &dyn Store) -> Result<Vec<Card>, ResourceError> {
+    let mut pool = set.items();
+    if pool.len() <= SMALL_SET {
+        for other in store.sets(&set.subject.id).await? {
+            if other.id != set.id {
+                pool.extend(other.items());
+            }
+        }
+    }
+    Ok(pool)
+}
+
+/// `?seed=`, or a fresh one if the quiz is just starting.
+fn seed(query: &Query) -> Result<u64, ResourceError> {
+    if query.0.contains_key("seed") {
+        query.parse("seed")
+    } else {
+        Ok(scheduler::now_millis().unsigned_abs())
+    }
+}
+
+async fn next_question(
+    set: &Set,
+    answer: Side,
+    seed: u64,
+    store: &dyn Store,
+) -> Result<Option<Question>, ResourceError> {
+    let Some(card) = next_card(set, answer.other())? else {
+        return Ok(None);
+    };
+    Ok(Some(question(
+        &card,
+        &pool(set, store).await?,
+        answer,
+        seed,
+    )))
+}
+
+pub async fn page(id: &str, query: &Query, store: &dyn Store) -> Result<Html, ResourceError> {
+    let set = store.get_set(id).await?;
+    let (answer, seed) = (answer_side(query)?, seed(query)?);
+    let question = next_question(&set, answer, seed, store).await?;
+    Ok(components::page(
+        format!("{} - quiz - flopcards", set.title),
+        body().child(h1("Quiz")).child(components::quiz_question(
+            &set.id,
+            question.as_ref(),
+            answer,
+            seed,
+        )),
+    ))
+}
+
+/// `/view/quiz?set={id}&seed={seed}`: the next question.
+pub async fn prompt(query: &Query, store: &dyn Store) -> Result<Div, ResourceError> {
+    let set = store.get_set(&query.get("set")?).await?;
+    let (answer, seed) = (answer_side(query)?, seed(query)?);
+    let question = next_question(&set, answer, seed, store).await?;
+    Ok(components::quiz_question(
+        &set.id,
+        question.as_ref(),
+        answer,
+        seed,
+    ))
+}
+
+/// Marks a `POST /sets/{set}/cards/{card}/quiz?choice={text}&seed={seed}`, given
+/// `{set}/cards/{card}`, by comparing the chosen text with the card, and records a
+/// good rating if it was right. The seed makes the question again to show the
+/// choices, which is only for show: the set may have changed since.
+pub async fn answer(query: &Query, path: &str, store: &dyn Store) -> Result<Div, ResourceError> {
+    let (set_id, card_id) = path
+        .rsplit_once("/cards/")
+        .ok_or_else(|| ResourceError::NotFound(format!("/sets/{path}/quiz")))?;
+    let (answer, seed) = (answer_side(query)?, query.parse("seed")?);
+    let choice = query.get("choice")?;
+    let set = store.get_set(set_id).await?;
+    let card = set
+        .items()
+        .into_iter()
+        .find(|card| card.id == card_id)
+        .ok_or_else(|| ResourceError::NotFound(format!("/sets/{path}/quiz")))?;
+    let rating = if choice.trim() == card.side(answer).trim() {
+        Rating::Good
+    } else {
+        Rating::Bad
+    };
+    let shown = query.parse("shown")?;
+    scheduler::record_review(&set, card_id, answer.other(), rating, shown, store).await?;
+    let question = question(&card, &pool(&set, store).await?, answer, seed);
+    Ok(components::quiz_feedback(
+        &set.id,
+        &question,
+        choice.trim(),
+        answer,
+        seed,
+    ))
+}
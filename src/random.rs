@@ -0,0 +1,48 @@
+//! A small seedable random number generator, so that generated questions and
+//! tests come out the same every time they are asked for with the same seed.
+
+/// SplitMix64: fast, tiny and good enough for shuffling cards.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub const fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// A generator seeded from `seed` and some text, such as a card id, so that each
+    /// card gets its own stream of numbers.
+    pub fn with_key(seed: u64, key: &str) -> Self {
+        Self::new(seed ^ hash(key))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number from `0` up to (but not including) `bound`, which must not be 0.
+    pub fn below(&mut self, bound: usize) -> usize {
+        #[allow(clippy::cast_possible_truncation)]
+        let index = (self.next_u64() % bound as u64) as usize;
+        index
+    }
+
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            items.swap(i, self.below(i + 1));
+        }
+    }
+}
+
+/// FNV-1a, which (unlike the standard library's hasher) is the same on every build.
+pub fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+    })
+}
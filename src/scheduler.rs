@@ -0,0 +1,454 @@
+use crate::cloze;
+use crate::data::{Card, Direction, Rating, ResourceError, Set, Side};
+use crate::store::Store;
+pub use fsrs::Fsrs;
+pub use leitner::Leitner;
+use serde::{Deserialize, Serialize};
+pub use sm2::Sm2;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    str::FromStr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+mod fsrs;
+mod leitner;
+mod sm2;
+
+pub const DAY: i64 = 60 * 60 * 24;
+
+/// How many reviews to log between refitting the FSRS weights.
+pub const OPTIMISE_EVERY: usize = 100;
+
+const PROGRESS_PATH: &str = "./progress.json";
+const REVIEW_LOG_PATH: &str = "./reviews.jsonl";
+
+/// Held while reading-modifying-writing the progress file so that two ratings
+/// submitted at once don't clobber each other.
+static PROGRESS_LOCK: Mutex<()> = Mutex::new(());
+static REVIEW_LOG_LOCK: Mutex<()> = Mutex::new(());
+
+/// Seconds since the unix epoch.
+pub fn now() -> i64 {
+    now_millis() / 1000
+}
+
+/// Milliseconds since the unix epoch.
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| {
+            i64::try_from(duration.as_millis()).unwrap_or(i64::MAX)
+        })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReviewState {
+    pub ease: f64,
+    /// Days until the next review.
+    pub interval: f64,
+    pub repetitions: u32,
+    pub lapses: u32,
+    /// Unix timestamp of when the card should next be shown.
+    pub due: i64,
+    pub last_review: Option<i64>,
+    /// FSRS memory stability in days, or 0 if FSRS hasn't scheduled this card.
+    #[serde(default)]
+    pub stability: f64,
+    /// FSRS difficulty from 1 to 10.
+    #[serde(default)]
+    pub difficulty: f64,
+    /// The Leitner box the card is in, from 1, or 0 if it has never been boxed.
+    #[serde(default)]
+    pub leitner_box: usize,
+}
+
+impl Default for ReviewState {
+    fn default() -> Self {
+        Self {
+            ease: 2.5,
+            interval: 0.0,
+            repetitions: 0,
+            lapses: 0,
+            due: 0,
+            last_review: None,
+            stability: 0.0,
+            difficulty: 0.0,
+            leitner_box: 0,
+        }
+    }
+}
+
+impl ReviewState {
+    pub const fn is_new(&self) -> bool {
+        self.last_review.is_none()
+    }
+
+    pub const fn is_due(&self, now: i64) -> bool {
+        self.due <= now
+    }
+}
+
+/// A single rating, as kept in the review log.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReviewLog {
+    pub set: String,
+    pub card: String,
+    pub rating: Rating,
+    /// Milliseconds between the card being shown and the rating being submitted.
+    pub response_time: u64,
+    pub timestamp: i64,
+}
+
+impl ReviewLog {
+    pub fn load_all() -> Result<Vec<Self>, ResourceError> {
+        match fs::read_to_string(REVIEW_LOG_PATH) {
+            Ok(log) => Ok(log
+                .lines()
+                .map(serde_json::from_str)
+                .collect::<Result<_, _>>()?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn append(&self) -> Result<(), ResourceError> {
+        let _guard = REVIEW_LOG_LOCK
+            .lock()
+            .map_err(|_| ResourceError::Custom("review log lock was poisoned".to_string()))?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(REVIEW_LOG_PATH)?;
+        writeln!(file, "{}", serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Sm2,
+    Fsrs,
+    Leitner,
+}
+
+impl Algorithm {
+    pub const fn all() -> [Self; 3] {
+        [Self::Sm2, Self::Fsrs, Self::Leitner]
+    }
+}
+
+impl Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            Self::Sm2 => "sm2",
+            Self::Fsrs => "fsrs",
+            Self::Leitner => "leitner",
+        };
+        write!(f, "{string}")
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = ResourceError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string {
+            "sm2" => Ok(Self::Sm2),
+            "fsrs" => Ok(Self::Fsrs),
+            "leitner" => Ok(Self::Leitner),
+            _ => Err(ResourceError::Custom(format!(
+                "'{string}' isn't a scheduling algorithm"
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Progress {
+    /// The algorithm used for sets that don't choose their own.
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub fsrs: Fsrs,
+    /// The boxes used by [`Algorithm::Leitner`].
+    #[serde(default)]
+    pub leitner: Leitner,
+    /// How many reviews have been recorded, which decides when the FSRS weights
+    /// are next refitted.
+    #[serde(default)]
+    pub reviews: usize,
+    /// Review state for every card that has been rated, keyed by set id then card id.
+    #[serde(default)]
+    pub cards: HashMap<String, HashMap<String, ReviewState>>,
+}
+
+impl Progress {
+    pub fn load() -> Result<Self, ResourceError> {
+        match fs::read_to_string(PROGRESS_PATH) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), ResourceError> {
+        fs::write(PROGRESS_PATH, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, set: &str, card: &str) -> ReviewState {
+        self.cards
+            .get(set)
+            .and_then(|cards| cards.get(card))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn review(
+        &self,
+        algorithm: Algorithm,
+        state: ReviewState,
+        rating: Rating,
+        now: i64,
+    ) -> ReviewState {
+        match algorithm {
+            Algorithm::Sm2 => Sm2::review(state, rating, now),
+            Algorithm::Fsrs => self.fsrs.review(state, rating, now),
+            Algorithm::Leitner => self.leitner.review(state, rating, now),
+        }
+    }
+
+    /// Loads the progress file, applies `update` and writes it back.
+    fn update<T>(update: impl FnOnce(&mut Self) -> T) -> Result<T, ResourceError> {
+        let _guard = PROGRESS_LOCK
+            .lock()
+            .map_err(|_| ResourceError::Custom("progress lock was poisoned".to_string()))?;
+        let mut progress = Self::load()?;
+        let result = update(&mut progress);
+        progress.save()?;
+        Ok(result)
+    }
+
+    /// Sets the algorithm used for sets that don't choose their own.
+    pub fn set_algorithm(algorithm: Algorithm) -> Result<Algorithm, ResourceError> {
+        Self::update(|progress| {
+            progress.algorithm = algorithm;
+            algorithm
+        })
+    }
+
+    /// Sets the days between reviews for each Leitner box, for every set scheduled
+    /// with [`Algorithm::Leitner`].
+    pub fn set_leitner(leitner: Leitner) -> Result<Leitner, ResourceError> {
+        Self::update(|progress| {
+            progress.leitner = leitner;
+            progress.leitner.clone()
+        })
+    }
+
+    /// Schedules the card's next review and writes it to disk, returning the new
+    /// state and how many reviews have now been recorded. `algorithm` is the set's
+    /// own choice, if it made one.
+    pub fn record(
+        set: &str,
+        card: &str,
+        rating: Rating,
+        algorithm: Option<Algorithm>,
+    ) -> Result<(ReviewState, usize), ResourceError> {
+        Self::update(|progress| {
+            let algorithm = algorithm.unwrap_or(progress.algorithm);
+            let state = progress.review(algorithm, progress.get(set, card), rating, now());
+            progress
+                .cards
+                .entry(set.to_string())
+                .or_default()
+                .insert(card.to_string(), state);
+            progress.reviews += 1;
+            (state, progress.reviews)
+        })
+    }
+
+    /// Refits the FSRS weights to the review log. Fitting takes a while, so it
+    /// starts from the weights as they are now and only holds the lock to store
+    /// the result, rather than holding up every rating in the meantime.
+    pub fn optimise(log: &[ReviewLog]) -> Result<Fsrs, ResourceError> {
+        let fsrs = Self::load()?.fsrs.optimise(log);
+        Self::update(|progress| {
+            progress.fsrs = fsrs;
+            fsrs
+        })
+    }
+
+    /// How many of the set's cards (each way round `direction` studies them) are in
+    /// each Leitner box, followed by how many have never been boxed.
+    pub fn leitner_boxes(&self, set: &Set, direction: Direction) -> (Vec<usize>, usize) {
+        let mut boxes = vec![0; self.leitner.boxes()];
+        let mut unboxed = 0;
+        for (card, front) in set
+            .items()
+            .into_iter()
+            .flat_map(|card| each_way(card, direction))
+        {
+            match self.get(&set.id, &card.review_id(front)).leitner_box {
+                0 => unboxed += 1,
+                number => boxes[number.min(boxes.len()) - 1] += 1,
+            }
+        }
+        (boxes, unboxed)
+    }
+
+    /// The cards that should be studied now, each with the side to show first:
+    /// overdue reviews first (most overdue at the front), followed by cards that
+    /// have never been seen. Each way round a card is scheduled on its own.
+    pub fn queue(
+        &self,
+        set: &str,
+        cards: Vec<Card>,
+        direction: Direction,
+        now: i64,
+    ) -> Vec<(Card, Side)> {
+        self.queue_sets(
+            cards
+                .into_iter()
+                .map(|card| (set.to_string(), card))
+                .collect(),
+            direction,
+            now,
+        )
+        .into_iter()
+        .map(|(_, card, front)| (card, front))
+        .collect()
+    }
+
+    /// Like [`Progress::queue`], but for cards from several sets at once, each
+    /// given with the id of its set. New cards are interleaved, taking the first
+    /// card of each set, then the second, and so on.
+    pub fn queue_sets(
+        &self,
+        cards: Vec<(String, Card)>,
+        direction: Direction,
+        now: i64,
+    ) -> Vec<(String, Card, Side)> {
+        let mut positions = HashMap::<String, usize>::new();
+        let mut due = cards
+            .into_iter()
+            .map(|(set, card)| {
+                let position = positions.entry(set.clone()).or_default();
+                *position += 1;
+                (*position, set, card)
+            })
+            .flat_map(|(position, set, card)| {
+                each_way(card, direction)
+                    .map(move |(card, front)| (position, set.clone(), card, front))
+            })
+            .map(|(position, set, card, front)| {
+                let state = self.get(&set, &card.review_id(front));
+                (state, position, set, card, front)
+            })
+            .filter(|(state, ..)| state.is_due(now))
+            .collect::<Vec<_>>();
+        due.sort_by_key(|(state, position, ..)| (state.is_new(), state.due, *position));
+        due.into_iter()
+            .map(|(_, _, set, card, front)| (set, card, front))
+            .collect()
+    }
+}
+
+/// The card once for each side `direction` shows first. A cloze is only ever
+/// asked with its blank showing.
+pub fn each_way(card: Card, direction: Direction) -> impl Iterator<Item = (Card, Side)> {
+    let cloze = cloze::is_item_id(&card.id);
+    direction
+        .fronts()
+        .iter()
+        .filter(move |front| !cloze || **front == Side::Term)
+        .map(move |front| (card.clone(), *front))
+}
+
+/// The card that should be studied next in the set with `front` shown first, if
+/// any are due.
+pub fn next_card(set: &Set, front: Side) -> Result<Option<Card>, ResourceError> {
+    Ok(Progress::load()?
+        .queue(&set.id, set.items(), front.into(), now())
+        .into_iter()
+        .next()
+        .map(|(card, _)| card))
+}
+
+/// A rating that has changed the card's schedule but hasn't been logged yet.
+#[must_use = "the rating still has to be logged with `ScheduledReview::log`"]
+pub struct ScheduledReview {
+    pub state: ReviewState,
+    review: ReviewLog,
+    /// How many reviews had been recorded, counting this one.
+    reviews: usize,
+}
+
+impl ScheduledReview {
+    /// Adds the rating to the store's review log, refitting the FSRS weights every
+    /// [`OPTIMISE_EVERY`] reviews.
+    pub async fn log(self, store: &dyn Store) -> Result<ReviewState, ResourceError> {
+        store.save_review(&self.review).await?;
+        if self.reviews % OPTIMISE_EVERY == 0 {
+            let log = store.reviews().await?;
+            tokio::task::spawn_blocking(move || {
+                if let Err(err) = Progress::optimise(&log) {
+                    eprintln!("Error optimising scheduler: {err:?}");
+                }
+            });
+        }
+        Ok(self.state)
+    }
+}
+
+/// Schedules the card's next review, leaving the rating to be logged afterwards so
+/// that callers holding a lock don't have to wait on the store. `shown` is when
+/// the card was put in front of the learner, in milliseconds, and `front` is the
+/// side they were shown first.
+pub fn schedule_review(
+    set: &Set,
+    card: &str,
+    front: Side,
+    rating: Rating,
+    shown: i64,
+) -> Result<ScheduledReview, ResourceError> {
+    let card = set
+        .items()
+        .into_iter()
+        .find(|set_card| set_card.id == card)
+        .ok_or_else(|| ResourceError::NotFound(format!("/sets/{}/cards/{card}", set.id)))?
+        .review_id(front);
+    let (state, reviews) = Progress::record(&set.id, &card, rating, set.algorithm)?;
+    Ok(ScheduledReview {
+        state,
+        review: ReviewLog {
+            set: set.id.clone(),
+            card,
+            rating,
+            response_time: u64::try_from(now_millis() - shown).unwrap_or_default(),
+            timestamp: now(),
+        },
+        reviews,
+    })
+}
+
+/// Schedules the card's next review and logs the rating in the store.
+pub async fn record_review(
+    set: &Set,
+    card: &str,
+    front: Side,
+    rating: Rating,
+    shown: i64,
+    store: &dyn Store,
+) -> Result<ReviewState, ResourceError> {
+    schedule_review(set, card, front, rating, shown)?
+        .log(store)
+        .await
+}
@@ -0,0 +1,325 @@
+use super::{ReviewLog, ReviewState, DAY};
+use crate::data::Rating;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const DECAY: f64 = -0.5;
+/// Chosen so that retrievability is 90% after `stability` days.
+const FACTOR: f64 = 19.0 / 81.0;
+
+const EPOCHS: usize = 200;
+const LEARNING_RATE: f64 = 0.01;
+const EPSILON: f64 = 1e-4;
+
+/// The range each weight is clamped to while optimising, taken from the reference
+/// implementation so that fitting to a small log can't produce nonsense intervals.
+const BOUNDS: [(f64, f64); 19] = [
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (0.01, 100.0),
+    (1.0, 10.0),
+    (0.001, 4.0),
+    (0.001, 4.0),
+    (0.001, 0.75),
+    (0.0, 4.5),
+    (0.0, 0.8),
+    (0.001, 3.5),
+    (0.001, 5.0),
+    (0.001, 0.25),
+    (0.001, 0.9),
+    (0.0, 4.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+    (0.0, 2.0),
+    (0.0, 2.0),
+];
+
+/// The Free Spaced Repetition Scheduler (FSRS-5). Each card's memory is modelled
+/// as a stability, the number of days until recall probability falls to 90%, and
+/// a difficulty between 1 and 10.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Fsrs {
+    pub weights: [f64; 19],
+    pub desired_retention: f64,
+}
+
+impl Default for Fsrs {
+    fn default() -> Self {
+        Self {
+            weights: Self::DEFAULT_WEIGHTS,
+            desired_retention: 0.9,
+        }
+    }
+}
+
+impl Fsrs {
+    pub const DEFAULT_WEIGHTS: [f64; 19] = [
+        0.40255, 1.18385, 3.173, 15.69105, 7.1949, 0.5345, 1.4604, 0.0046, 1.54575, 0.1192,
+        1.01925, 1.9395, 0.11, 0.29605, 2.2698, 0.2315, 2.9898, 0.51655, 0.6621,
+    ];
+
+    /// FSRS grades answers as again (1), hard (2), good (3) or easy (4).
+    pub const fn grade(rating: Rating) -> u8 {
+        match rating {
+            Rating::Terrible | Rating::Bad => 1,
+            Rating::Ok => 2,
+            Rating::Good => 3,
+            Rating::Perfect => 4,
+        }
+    }
+
+    /// The probability of recalling a card `elapsed_days` after it was last reviewed.
+    pub fn retrievability(elapsed_days: f64, stability: f64) -> f64 {
+        (FACTOR * elapsed_days / stability + 1.0).powf(DECAY)
+    }
+
+    /// Days until retrievability falls to the desired retention.
+    pub fn interval(&self, stability: f64) -> f64 {
+        (stability / FACTOR * (self.desired_retention.powf(1.0 / DECAY) - 1.0))
+            .round()
+            .max(1.0)
+    }
+
+    fn initial_stability(&self, grade: u8) -> f64 {
+        self.weights[usize::from(grade - 1)].max(0.1)
+    }
+
+    fn initial_difficulty(&self, grade: u8) -> f64 {
+        (self.weights[4] - (self.weights[5] * f64::from(grade - 1)).exp() + 1.0).clamp(1.0, 10.0)
+    }
+
+    fn next_difficulty(&self, difficulty: f64, grade: u8) -> f64 {
+        let delta = -self.weights[6] * (f64::from(grade) - 3.0);
+        let difficulty = delta.mul_add((10.0 - difficulty) / 9.0, difficulty);
+        // mean reversion towards the difficulty of a card first rated easy
+        self.weights[7]
+            .mul_add(
+                self.initial_difficulty(4),
+                (1.0 - self.weights[7]) * difficulty,
+            )
+            .clamp(1.0, 10.0)
+    }
+
+    fn next_stability(
+        &self,
+        stability: f64,
+        difficulty: f64,
+        retrievability: f64,
+        grade: u8,
+    ) -> f64 {
+        let w = &self.weights;
+        if grade == 1 {
+            let forgotten = w[11]
+                * difficulty.powf(-w[12])
+                * ((stability + 1.0).powf(w[13]) - 1.0)
+                * (w[14] * (1.0 - retrievability)).exp();
+            forgotten.min(stability)
+        } else {
+            let hard_penalty = if grade == 2 { w[15] } else { 1.0 };
+            let easy_bonus = if grade == 4 { w[16] } else { 1.0 };
+            stability
+                * (w[8].exp()
+                    * (11.0 - difficulty)
+                    * stability.powf(-w[9])
+                    * ((w[10] * (1.0 - retrievability)).exp() - 1.0)
+                    * hard_penalty
+                    * easy_bonus
+                    + 1.0)
+        }
+    }
+
+    /// Reviews on the same day barely test long-term memory, so they get a
+    /// separate, gentler update.
+    fn short_term_stability(&self, stability: f64, grade: u8) -> f64 {
+        stability * (self.weights[17] * (f64::from(grade) - 3.0 + self.weights[18])).exp()
+    }
+
+    /// The `(stability, difficulty)` after a review, where `memory` is `None` for a
+    /// card that has never been reviewed.
+    fn step(&self, memory: Option<(f64, f64)>, elapsed_days: f64, grade: u8) -> (f64, f64) {
+        let Some((stability, difficulty)) = memory else {
+            return (
+                self.initial_stability(grade),
+                self.initial_difficulty(grade),
+            );
+        };
+        let stability = if elapsed_days < 1.0 {
+            self.short_term_stability(stability, grade)
+        } else {
+            let retrievability = Self::retrievability(elapsed_days, stability);
+            self.next_stability(stability, difficulty, retrievability, grade)
+        };
+        (stability.max(0.01), self.next_difficulty(difficulty, grade))
+    }
+
+    pub fn review(&self, mut state: ReviewState, rating: Rating, now: i64) -> ReviewState {
+        let grade = Self::grade(rating);
+        // cards last scheduled by another algorithm have no memory state yet
+        #[allow(clippy::cast_precision_loss)]
+        let (stability, difficulty) = match state.last_review.filter(|_| state.stability > 0.0) {
+            Some(last_review) => self.step(
+                Some((state.stability, state.difficulty)),
+                (now - last_review) as f64 / DAY as f64,
+                grade,
+            ),
+            None => self.step(None, 0.0, grade),
+        };
+        if grade == 1 {
+            state.repetitions = 0;
+            state.lapses += 1;
+        } else {
+            state.repetitions += 1;
+        }
+        state.stability = stability;
+        state.difficulty = difficulty;
+        state.interval = self.interval(stability);
+        state.last_review = Some(now);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let interval = (state.interval * DAY as f64) as i64;
+        state.due = now + interval;
+        state
+    }
+
+    /// Fits the weights to a review log by gradient descent on the log loss of the
+    /// predicted recall probability at every review after a card's first.
+    pub fn optimise(&self, log: &[ReviewLog]) -> Self {
+        let histories = histories(log);
+        let mut fsrs = *self;
+        if histories.iter().all(|history| history.len() < 2) {
+            return fsrs;
+        }
+        for _ in 0..EPOCHS {
+            let loss = fsrs.loss(&histories);
+            let mut gradient = [0.0; 19];
+            for (index, slope) in gradient.iter_mut().enumerate() {
+                let mut nudged = fsrs;
+                nudged.weights[index] += EPSILON;
+                *slope = (nudged.loss(&histories) - loss) / EPSILON;
+            }
+            for ((weight, slope), (low, high)) in fsrs.weights.iter_mut().zip(gradient).zip(BOUNDS)
+            {
+                *weight = (LEARNING_RATE * (high - low))
+                    .mul_add(-slope, *weight)
+                    .clamp(low, high);
+            }
+        }
+        fsrs
+    }
+
+    fn loss(&self, histories: &[Vec<(f64, u8)>]) -> f64 {
+        let mut total = 0.0;
+        let mut count = 0_u32;
+        for history in histories {
+            let mut memory = None;
+            for &(elapsed_days, grade) in history {
+                if let Some((stability, _)) = memory {
+                    if elapsed_days >= 1.0 {
+                        let retrievability = Self::retrievability(elapsed_days, stability)
+                            .clamp(EPSILON, 1.0 - EPSILON);
+                        total -= if grade > 1 {
+                            retrievability.ln()
+                        } else {
+                            (1.0 - retrievability).ln()
+                        };
+                        count += 1;
+                    }
+                }
+                memory = Some(self.step(memory, elapsed_days, grade));
+            }
+        }
+        if count == 0 {
+            0.0
+        } else {
+            total / f64::from(count)
+        }
+    }
+}
+
+/// Each card's reviews in order, as `(days since the previous review, grade)`.
+fn histories(log: &[ReviewLog]) -> Vec<Vec<(f64, u8)>> {
+    let mut cards = HashMap::<_, Vec<_>>::new();
+    for review in log {
+        cards
+            .entry((&review.set, &review.card))
+            .or_default()
+            .push(review);
+    }
+    cards
+        .into_values()
+        .map(|mut reviews| {
+            reviews.sort_by_key(|review| review.timestamp);
+            let mut previous = None;
+            reviews
+                .into_iter()
+                .map(|review| {
+                    #[allow(clippy::cast_precision_loss)]
+                    let elapsed_days = previous.map_or(0.0, |previous| {
+                        (review.timestamp - previous) as f64 / DAY as f64
+                    });
+                    previous = Some(review.timestamp);
+                    (elapsed_days, Fsrs::grade(review.rating))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_match_stability_at_ninety_percent_retention() {
+        let fsrs = Fsrs::default();
+        assert!((Fsrs::retrievability(10.0, 10.0) - 0.9).abs() < 1e-9);
+        assert!((fsrs.interval(10.0) - 10.0).abs() < f64::EPSILON);
+        assert!((fsrs.interval(0.2) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn new_cards_start_from_the_initial_weights() {
+        let fsrs = Fsrs::default();
+        let easy = fsrs.review(ReviewState::default(), Rating::Perfect, 0);
+        assert!((easy.stability - Fsrs::DEFAULT_WEIGHTS[3]).abs() < 1e-9);
+        assert!((easy.interval - 16.0).abs() < f64::EPSILON);
+        assert_eq!(easy.due, 16 * DAY);
+        assert_eq!(easy.repetitions, 1);
+
+        let again = fsrs.review(ReviewState::default(), Rating::Terrible, 0);
+        assert!((again.interval - 1.0).abs() < f64::EPSILON);
+        assert_eq!((again.repetitions, again.lapses), (0, 1));
+        assert!(again.difficulty > easy.difficulty);
+    }
+
+    #[test]
+    fn remembering_grows_stability_and_forgetting_shrinks_it() {
+        let fsrs = Fsrs::default();
+        let first = fsrs.review(ReviewState::default(), Rating::Good, 0);
+        let due = first.due;
+        let remembered = fsrs.review(first, Rating::Good, due);
+        let forgotten = fsrs.review(first, Rating::Bad, due);
+        assert!(remembered.stability > first.stability);
+        assert!(remembered.interval > first.interval);
+        assert!(forgotten.stability <= first.stability);
+        assert_eq!(forgotten.lapses, 1);
+    }
+
+    #[test]
+    fn cards_from_other_algorithms_are_treated_as_new() {
+        let fsrs = Fsrs::default();
+        let sm2 = ReviewState {
+            last_review: Some(0),
+            interval: 30.0,
+            ..ReviewState::default()
+        };
+        let state = fsrs.review(sm2, Rating::Good, 30 * DAY);
+        assert!((state.stability - Fsrs::DEFAULT_WEIGHTS[2]).abs() < 1e-9);
+    }
+
+    #[test]
+    fn optimising_without_repeat_reviews_keeps_the_weights() {
+        let fsrs = Fsrs::default();
+        assert_eq!(fsrs.optimise(&[]), fsrs);
+    }
+}
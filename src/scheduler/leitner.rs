@@ -0,0 +1,146 @@
+use super::{ReviewState, DAY};
+use crate::data::{Rating, ResourceError};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Leitner boxes: a card moves up a box each time it is known and back to the first
+/// box when it is forgotten, and each box is reviewed less often than the last.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Leitner {
+    /// Days between reviews for the cards in each box, one per box.
+    pub intervals: Vec<f64>,
+}
+
+impl Default for Leitner {
+    fn default() -> Self {
+        Self {
+            intervals: vec![1.0, 3.0, 7.0, 14.0, 30.0],
+        }
+    }
+}
+
+impl Leitner {
+    pub fn boxes(&self) -> usize {
+        self.intervals.len().max(1)
+    }
+
+    /// Terrible and bad send the card back to the first box, ok keeps it where it
+    /// is and good and perfect move it up one.
+    pub fn review(&self, mut state: ReviewState, rating: Rating, now: i64) -> ReviewState {
+        state.leitner_box = match rating {
+            Rating::Terrible | Rating::Bad => {
+                if !state.is_new() {
+                    state.lapses += 1;
+                }
+                state.repetitions = 0;
+                1
+            }
+            Rating::Ok => {
+                state.repetitions += 1;
+                state.leitner_box.max(1)
+            }
+            Rating::Good | Rating::Perfect => {
+                state.repetitions += 1;
+                (state.leitner_box + 1).min(self.boxes())
+            }
+        };
+        state.interval = self
+            .intervals
+            .get(state.leitner_box - 1)
+            .copied()
+            .unwrap_or(1.0);
+        state.last_review = Some(now);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let interval = (state.interval * DAY as f64) as i64;
+        state.due = now + interval;
+        state
+    }
+}
+
+/// The intervals as days separated by commas, such as `1, 3, 7`.
+impl Display for Leitner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let intervals = self
+            .intervals
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        write!(f, "{}", intervals.join(", "))
+    }
+}
+
+impl FromStr for Leitner {
+    type Err = ResourceError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let intervals = string
+            .split(',')
+            .map(str::trim)
+            .filter(|interval| !interval.is_empty())
+            .map(|interval| {
+                interval
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|days| days.is_finite() && *days > 0.0)
+                    .ok_or_else(|| {
+                        ResourceError::Custom(format!("'{interval}' isn't a number of days"))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if intervals.is_empty() {
+            return Err(ResourceError::Custom(
+                "there must be at least one box".to_string(),
+            ));
+        }
+        Ok(Self { intervals })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cards_move_up_a_box_when_known_and_back_to_the_first_when_forgotten() {
+        let leitner = Leitner::default();
+        let mut state = ReviewState::default();
+        for (day, rating) in [Rating::Good, Rating::Perfect, Rating::Ok]
+            .into_iter()
+            .enumerate()
+        {
+            state = leitner.review(state, rating, i64::try_from(day).unwrap() * DAY);
+        }
+        assert_eq!((state.leitner_box, state.repetitions), (2, 3));
+        assert!((state.interval - 3.0).abs() < f64::EPSILON);
+        assert_eq!(state.due, 2 * DAY + 3 * DAY);
+
+        state = leitner.review(state, Rating::Bad, 3 * DAY);
+        assert_eq!(state.leitner_box, 1);
+        assert_eq!((state.repetitions, state.lapses), (0, 1));
+        assert!((state.interval - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn cards_stay_in_the_last_box() {
+        let leitner = Leitner {
+            intervals: vec![1.0, 2.0],
+        };
+        let mut state = ReviewState::default();
+        for day in 0..4 {
+            state = leitner.review(state, Rating::Good, day * DAY);
+        }
+        assert_eq!(state.leitner_box, 2);
+        assert!((state.interval - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn intervals_are_read_as_days_separated_by_commas() {
+        let leitner = "1, 2.5,10,".parse::<Leitner>().unwrap();
+        assert_eq!(leitner.intervals, [1.0, 2.5, 10.0]);
+        assert_eq!(leitner.to_string(), "1, 2.5, 10");
+        assert!("".parse::<Leitner>().is_err());
+        assert!("1, soon".parse::<Leitner>().is_err());
+        assert!("0".parse::<Leitner>().is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use super::{ReviewState, DAY};
+use crate::data::Rating;
+
+/// The SM-2 algorithm, as used by SuperMemo 2 and (loosely) Anki.
+pub struct Sm2;
+
+impl Sm2 {
+    const MIN_EASE: f64 = 1.3;
+
+    /// SM-2 grades answers from 0 to 5, where anything below 3 is a lapse.
+    pub const fn quality(rating: Rating) -> u8 {
+        match rating {
+            Rating::Terrible => 0,
+            Rating::Bad => 2,
+            Rating::Ok => 3,
+            Rating::Good => 4,
+            Rating::Perfect => 5,
+        }
+    }
+
+    pub fn review(mut state: ReviewState, rating: Rating, now: i64) -> ReviewState {
+        let quality = Self::quality(rating);
+        if quality < 3 {
+            state.repetitions = 0;
+            state.lapses += 1;
+            state.interval = 1.0;
+        } else {
+            state.repetitions += 1;
+            state.interval = match state.repetitions {
+                1 => 1.0,
+                2 => 6.0,
+                _ => (state.interval * state.ease).round(),
+            };
+        }
+        let penalty = f64::from(5 - quality);
+        state.ease = penalty
+            .mul_add(-penalty.mul_add(0.02, 0.08), state.ease + 0.1)
+            .max(Self::MIN_EASE);
+        state.last_review = Some(now);
+        #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+        let interval = (state.interval * DAY as f64) as i64;
+        state.due = now + interval;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_grow_by_ease_after_the_first_two() {
+        let mut state = ReviewState::default();
+        let mut intervals = Vec::new();
+        for day in 0..4 {
+            state = Sm2::review(state, Rating::Good, day * DAY);
+            intervals.push(state.interval);
+        }
+        assert_eq!(intervals, [1.0, 6.0, 15.0, 38.0]);
+        assert_eq!(state.repetitions, 4);
+        assert!((state.ease - 2.5).abs() < 1e-9);
+        assert_eq!(state.last_review, Some(3 * DAY));
+        assert_eq!(state.due, 3 * DAY + 38 * DAY);
+    }
+
+    #[test]
+    fn ratings_move_the_ease() {
+        let ease = |rating| Sm2::review(ReviewState::default(), rating, 0).ease;
+        assert!((ease(Rating::Perfect) - 2.6).abs() < 1e-9);
+        assert!((ease(Rating::Ok) - 2.36).abs() < 1e-9);
+        assert!((ease(Rating::Terrible) - 1.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lapses_start_again_without_going_below_the_minimum_ease() {
+        let mut state = ReviewState::default();
+        for day in 0..3 {
+            state = Sm2::review(state, Rating::Good, day * DAY);
+        }
+        for day in 3..6 {
+            state = Sm2::review(state, Rating::Terrible, day * DAY);
+        }
+        assert_eq!(state.repetitions, 0);
+        assert_eq!(state.lapses, 3);
+        assert!((state.interval - 1.0).abs() < f64::EPSILON);
+        assert!((state.ease - Sm2::MIN_EASE).abs() < 1e-9);
+    }
+}
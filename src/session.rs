@@ -0,0 +1,450 @@
+//! Study sessions kept on the server, so that a reload (or coming back the next
+//! day) picks up where the learner left off. Sessions are stored in
+//! `./sessions.json`.
+use crate::data::{Direction, Query, Rating, ResourceError, Set, Side};
+use crate::scheduler::{self, each_way, Progress, DAY};
+use crate::study::{combined_items, Scope};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+const SESSIONS_PATH: &str = "./sessions.json";
+
+/// Seconds between showings of a new card that is being learnt.
+pub const LEARNING_STEPS: [i64; 2] = [60, 10 * 60];
+
+/// Seconds between showings of a forgotten card that is being relearnt.
+pub const RELEARNING_STEPS: [i64; 1] = [10 * 60];
+
+/// How many good ratings in a row a card needs in cram mode if not told otherwise.
+pub const CRAM_STREAK: u32 = 2;
+
+/// How many other cards come between a crammed card and its next showing.
+pub const CRAM_GAP: usize = 4;
+
+/// How many of the weakest cards to show at the end of a cram.
+pub const WEAKEST_CARDS: usize = 10;
+
+/// Sessions that haven't been touched for this long are forgotten.
+pub const SESSION_LIFETIME: i64 = 7 * DAY;
+
+static SESSIONS_LOCK: Mutex<()> = Mutex::new(());
+
+/// One card, shown one way round.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Item {
+    pub set: String,
+    pub card: String,
+    pub front: Side,
+}
+
+impl Item {
+    /// The query string [`Item::from_query`] reads back as this item.
+    pub fn to_query(&self) -> String {
+        url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("set", &self.set)
+            .append_pair("card", &self.card)
+            .append_pair("front", &self.front.to_string())
+            .finish()
+    }
+
+    pub fn from_query(query: &Query) -> Result<Self, ResourceError> {
+        Ok(Self {
+            set: query.get("set")?,
+            card: query.get("card")?,
+            front: query.parse("front")?,
+        })
+    }
+}
+
+/// A card being shown again within the session until it sticks.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Learning {
+    pub item: Item,
+    /// Index into [`LEARNING_STEPS`], or [`RELEARNING_STEPS`] if `relearning`.
+    pub step: usize,
+    pub relearning: bool,
+    /// Unix timestamp of when the card should be shown again.
+    pub due: i64,
+}
+
+fn steps(relearning: bool) -> &'static [i64] {
+    if relearning {
+        &RELEARNING_STEPS
+    } else {
+        &LEARNING_STEPS
+    }
+}
+
+impl Learning {
+    /// The card at `step`, due after that step's delay.
+    fn at(item: Item, relearning: bool, step: usize, now: i64) -> Self {
+        let steps = steps(relearning);
+        Self {
+            item,
+            step,
+            relearning,
+            due: now + steps[step.min(steps.len() - 1)],
+        }
+    }
+}
+
+/// How a card has gone in cram mode.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CramCard {
+    pub item: Item,
+    /// Good or perfect ratings in a row.
+    pub streak: u32,
+    pub attempts: u32,
+    /// Ratings worse than good.
+    pub misses: u32,
+}
+
+/// Cram mode: every card is repeated until it is rated good or perfect `streak`
+/// times in a row, and none of it is recorded with the scheduler.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cram {
+    pub streak: u32,
+    pub cards: Vec<CramCard>,
+}
+
+impl Cram {
+    /// The cards missed most often, worst first.
+    pub fn weakest(&self) -> Vec<&CramCard> {
+        let mut cards = self
+            .cards
+            .iter()
+            .filter(|card| card.misses > 0)
+            .collect::<Vec<_>>();
+        cards.sort_by_key(|card| (Reverse(card.misses), Reverse(card.attempts)));
+        cards.truncate(WEAKEST_CARDS);
+        cards
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StudySession {
+    pub id: String,
+    pub title: String,
+    /// The [`Scope`] the session was started from, as a query string.
+    pub scope: String,
+    pub direction: Direction,
+    /// Cards still to be shown for the first time, in order.
+    pub queue: Vec<Item>,
+    /// The card in front of the learner.
+    pub current: Option<Item>,
+    pub learning: Vec<Learning>,
+    /// Cards that are done for this session.
+    pub answered: Vec<Item>,
+    /// Cards whose first answer has been recorded with the scheduler. Later answers
+    /// in the same session only move the card through its learning steps.
+    pub recorded: Vec<Item>,
+    /// Set if this is a cram rather than a scheduled review.
+    #[serde(default)]
+    pub cram: Option<Cram>,
+    pub started: i64,
+    pub updated: i64,
+}
+
+impl StudySession {
+    /// A session over whatever is due in `sets` right now.
+    pub fn new(
+        title: String,
+        scope: &Scope,
+        direction: Direction,
+        sets: &[Set],
+    ) -> Result<Self, ResourceError> {
+        let now = scheduler::now();
+        let queue = Progress::load()?
+            .queue_sets(combined_items(sets), direction, now)
+            .into_iter()
+            .map(|(set, card, front)| Item {
+                set,
+                card: card.id,
+                front,
+            })
+            .collect();
+        let mut session = Self {
+            id: Uuid::new_v4().to_string(),
+            title,
+            scope: scope.to_query(),
+            direction,
+            queue,
+            current: None,
+            learning: Vec::new(),
+            answered: Vec::new(),
+            recorded: Vec::new(),
+            cram: None,
+            started: now,
+            updated: now,
+        };
+        session.advance(now);
+        Ok(session)
+    }
+
+    /// A cram over every card in `sets`, due or not.
+    pub fn cram(
+        title: String,
+        scope: &Scope,
+        direction: Direction,
+        sets: &[Set],
+        streak: u32,
+    ) -> Self {
+        let queue = combined_items(sets)
+            .into_iter()
+            .flat_map(|(set, card)| {
+                each_way(card, direction).map(move |(card, front)| Item {
+                    set: set.clone(),
+                    card: card.id,
+                    front,
+                })
+            })
+            .collect::<Vec<_>>();
+        let cards = queue
+            .iter()
+            .map(|item| CramCard {
+                item: item.clone(),
+                streak: 0,
+                attempts: 0,
+                misses: 0,
+            })
+            .collect();
+        let now = scheduler::now();
+        let mut session = Self {
+            id: Uuid::new_v4().to_string(),
+            title,
+            scope: scope.to_query(),
+            direction,
+            queue,
+            current: None,
+            learning: Vec::new(),
+            answered: Vec::new(),
+            recorded: Vec::new(),
+            cram: Some(Cram {
+                streak: streak.max(1),
+                cards,
+            }),
+            started: now,
+            updated: now,
+        };
+        session.advance(now);
+        session
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current.is_none()
+    }
+
+    /// Moves on to the next card: a learning card if one is due, otherwise the next
+    /// card in the queue, otherwise the learning card due soonest.
+    fn advance(&mut self, now: i64) {
+        let soonest = self
+            .learning
+            .iter()
+            .min_by_key(|learning| learning.due)
+            .map(|learning| (learning.due, learning.item.clone()));
+        self.current = match soonest {
+            Some((due, item)) if due <= now => Some(item),
+            _ if !self.queue.is_empty() => Some(self.queue.remove(0)),
+            soonest => soonest.map(|(_, item)| item),
+        };
+    }
+
+    /// Whether the current card's answer should be recorded with the scheduler:
+    /// only its first in the session, and never in a cram.
+    pub fn should_record(&self) -> bool {
+        self.cram.is_none()
+            && self
+                .current
+                .as_ref()
+                .is_some_and(|item| !self.recorded.contains(item))
+    }
+
+    /// Rates the current card and moves on. `was_new` is whether the card had never
+    /// been reviewed before this session.
+    ///
+    /// Failed cards go (back) to the first learning or relearning step. New cards
+    /// that are passed skip the first step, and perfect ones are done straight away.
+    pub fn answer(&mut self, rating: Rating, was_new: bool, now: i64) -> Result<(), ResourceError> {
+        let item = self
+            .current
+            .take()
+            .ok_or_else(|| ResourceError::Custom("the session is finished".to_string()))?;
+        if let Some(cram) = &mut self.cram {
+            let card = cram
+                .cards
+                .iter_mut()
+                .find(|card| card.item == item)
+                .ok_or_else(|| {
+                    ResourceError::Custom("that card isn't being crammed".to_string())
+                })?;
+            card.attempts += 1;
+            if matches!(rating, Rating::Good | Rating::Perfect) {
+                card.streak += 1;
+            } else {
+                card.streak = 0;
+                card.misses += 1;
+            }
+            if card.streak >= cram.streak {
+                self.answered.push(item);
+            } else {
+                self.queue.insert(CRAM_GAP.min(self.queue.len()), item);
+            }
+            self.updated = now;
+            self.advance(now);
+            return Ok(());
+        }
+        if !self.recorded.contains(&item) {
+            self.recorded.push(item.clone());
+        }
+        let failed = matches!(rating, Rating::Terrible | Rating::Bad);
+        let learning = self
+            .learning
+            .iter()
+            .position(|learning| learning.item == item)
+            .map(|index| self.learning.remove(index));
+        let next = match learning {
+            Some(learning) if failed => Some(Learning::at(item, learning.relearning, 0, now)),
+            Some(learning) if learning.step + 1 < steps(learning.relearning).len() => Some(
+                Learning::at(item, learning.relearning, learning.step + 1, now),
+            ),
+            Some(_) => {
+                self.answered.push(item);
+                None
+            }
+            None if failed => Some(Learning::at(item, !was_new, 0, now)),
+            None if was_new && rating != Rating::Perfect => Some(Learning::at(item, false, 1, now)),
+            None => {
+                self.answered.push(item);
+                None
+            }
+        };
+        self.learning.extend(next);
+        self.updated = now;
+        self.advance(now);
+        Ok(())
+    }
+
+    /// Drops the current card without rating it, such as when it has been deleted.
+    pub fn skip(&mut self, now: i64) {
+        if let Some(item) = self.current.take() {
+            self.learning.retain(|learning| learning.item != item);
+        }
+        self.advance(now);
+    }
+}
+
+/// Every session, keyed by id.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Sessions(pub HashMap<String, StudySession>);
+
+impl Sessions {
+    pub fn load() -> Result<Self, ResourceError> {
+        match fs::read_to_string(SESSIONS_PATH) {
+            Ok(json) => Ok(serde_json::from_str(&json)?),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), ResourceError> {
+        fs::write(SESSIONS_PATH, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Loads the sessions, applies `update` and writes them back, forgetting any
+    /// that have been left alone for too long.
+    pub fn update<T>(update: impl FnOnce(&mut Self) -> T) -> Result<T, ResourceError> {
+        let _guard = SESSIONS_LOCK
+            .lock()
+            .map_err(|_| ResourceError::Custom("sessions lock was poisoned".to_string()))?;
+        let mut sessions = Self::load()?;
+        let cutoff = scheduler::now() - SESSION_LIFETIME;
+        sessions.0.retain(|_, session| session.updated > cutoff);
+        let result = update(&mut sessions);
+        sessions.save()?;
+        Ok(result)
+    }
+
+    pub fn get(&self, id: &str) -> Result<&StudySession, ResourceError> {
+        self.0
+            .get(id)
+            .ok_or_else(|| ResourceError::NotFound(format!("/session/{id}")))
+    }
+
+    /// Sessions that still have cards to go, most recently studied first.
+    pub fn unfinished(&self) -> Vec<&StudySession> {
+        let mut sessions = self
+            .0
+            .values()
+            .filter(|session| !session.is_finished())
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|session| Reverse(session.updated));
+        sessions
+    }
+
+    /// The unfinished session over the same scope and direction (and the same
+    /// cram streak, if `cram` is given), or a new one. Both happen under the lock,
+    /// so two requests at once can't start two sessions.
+    pub fn resume_or_start(
+        title: String,
+        scope: &Scope,
+        direction: Direction,
+        cram: Option<u32>,
+        sets: &[Set],
+    ) -> Result<StudySession, ResourceError> {
+        let query = scope.to_query();
+        Self::update(|sessions| {
+            let existing = sessions
+                .unfinished()
+                .into_iter()
+                .find(|session| {
+                    session.scope == query
+                        && session.direction == direction
+                        && session.cram.as_ref().map(|session| session.streak) == cram
+                })
+                .cloned();
+            if let Some(session) = existing {
+                return Ok(session);
+            }
+            let session = match cram {
+                Some(streak) => StudySession::cram(title, scope, direction, sets, streak),
+                None => StudySession::new(title, scope, direction, sets)?,
+            };
+            sessions.0.insert(session.id.clone(), session.clone());
+            Ok(session)
+        })?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::fixtures::algebra;
+
+    #[test]
+    fn crams_are_never_recorded_and_repeat_until_the_streak() {
+        let scope = Scope::Sets(vec!["maths/algebra".to_string()]);
+        let mut session = StudySession::cram(
+            "cram".to_string(),
+            &scope,
+            Direction::Forward,
+            &[algebra()],
+            2,
+        );
+        for rating in [Rating::Good, Rating::Bad, Rating::Good] {
+            assert!(!session.should_record());
+            session.answer(rating, true, 0).unwrap();
+            assert!(!session.is_finished());
+        }
+        session.answer(Rating::Perfect, true, 0).unwrap();
+        assert!(session.is_finished());
+        let card = &session.cram.as_ref().unwrap().cards[0];
+        assert_eq!((card.attempts, card.misses), (4, 1));
+        assert!(session.recorded.is_empty());
+    }
+}
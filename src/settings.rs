@@ -0,0 +1,38 @@
+//! `/settings`: the learner's own scheduling settings. They apply to every set,
+//! unlike the algorithm chosen for a set in its editor.
+use crate::components;
+use crate::data::{body_to_string, Query, ResourceError};
+use crate::scheduler::Progress;
+use html_builder::prelude::*;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::{Request, Response};
+
+pub fn page() -> Result<Html, ResourceError> {
+    let progress = Progress::load()?;
+    Ok(components::page(
+        "settings - flopcards",
+        body()
+            .child(h1("Settings"))
+            .child(a("/").class("underline").text("home"))
+            .child(components::default_algorithm_picker(progress.algorithm))
+            .child(components::leitner_intervals_form(&progress.leitner)),
+    ))
+}
+
+/// Handles the `POST`s made by the settings page under `/settings/{path}`.
+pub async fn update(
+    request: Request<hyper::body::Incoming>,
+    path: &str,
+) -> Result<Response<Full<Bytes>>, ResourceError> {
+    if path == "leitner" {
+        let form = Query::from_str(&body_to_string(request).await?);
+        let leitner = Progress::set_leitner(form.parse("intervals")?)?;
+        return components::leitner_intervals_form(&leitner).response_ok();
+    }
+    if let Some(algorithm) = path.strip_prefix("algorithm/") {
+        let algorithm = Progress::set_algorithm(algorithm.parse()?)?;
+        return components::default_algorithm_picker(algorithm).response_ok();
+    }
+    Err(ResourceError::NotFound(format!("/settings/{path}")))
+}
@@ -0,0 +1,511 @@
+use crate::data::{Card, Folder, ResourceError, Set, Subject};
+use crate::database::Database;
+use crate::scheduler::{Algorithm, ReviewLog};
+use futures::{future::BoxFuture, FutureExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+/// Somewhere subjects and sets are kept. Handlers only talk to the store held in
+/// the app state, so they work the same against files, a database or memory.
+pub trait Store: Send + Sync {
+    fn subjects(&self) -> BoxFuture<'_, Result<Vec<Subject>, ResourceError>>;
+
+    /// Every folder in the subject, however deeply nested.
+    fn folders<'a>(&'a self, subject: &'a str)
+        -> BoxFuture<'a, Result<Vec<Folder>, ResourceError>>;
+
+    /// Every set in the subject, including those inside folders.
+    fn sets<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>>;
+
+    fn get_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Set, ResourceError>>;
+
+    /// The sets that were remixed from the set with this id.
+    fn remixes<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>>;
+
+    /// Creates the set, or replaces the set with the same id.
+    fn save_set<'a>(&'a self, set: &'a Set) -> BoxFuture<'a, Result<(), ResourceError>>;
+
+    fn delete_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), ResourceError>>;
+
+    /// Creates the folder if there isn't one with its id already.
+    fn save_folder<'a>(&'a self, folder: &'a Folder) -> BoxFuture<'a, Result<(), ResourceError>>;
+
+    /// Adds a rating to the review log.
+    fn save_review<'a>(&'a self, review: &'a ReviewLog)
+        -> BoxFuture<'a, Result<(), ResourceError>>;
+
+    /// The whole review log, oldest first.
+    fn reviews(&self) -> BoxFuture<'_, Result<Vec<ReviewLog>, ResourceError>>;
+}
+
+#[derive(Deserialize)]
+struct SubjectConfig {
+    color: String,
+}
+
+/// How a set is written to `{subject}/{name}.toml`. The id and subject come from
+/// the file's path rather than its contents.
+#[derive(Serialize, Deserialize)]
+struct SetFile {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    algorithm: Option<Algorithm>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remix_of: Option<String>,
+    cards: Vec<Card>,
+}
+
+/// Subjects are directories containing a `mod.toml` with their colour. Below them,
+/// directories are folders and TOML files are sets, both identified by their path
+/// without the extension.
+pub struct FileStore {
+    root: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn set_path(&self, id: &str) -> Result<PathBuf, ResourceError> {
+        if id
+            .split('/')
+            .any(|segment| segment.is_empty() || segment.starts_with('.'))
+        {
+            return Err(ResourceError::NotFound(format!("/sets/{id}")));
+        }
+        Ok(self.root.join(format!("{id}.toml")))
+    }
+
+    fn read_subject(&self, id: &str) -> Result<Subject, ResourceError> {
+        let config = fs::read_to_string(self.root.join(id).join("mod.toml"))?;
+        Ok(Subject {
+            id: id.to_string(),
+            name: id.to_string(),
+            color: toml::from_str::<SubjectConfig>(&config)?.color,
+        })
+    }
+
+    fn read_subjects(&self) -> Result<Vec<Subject>, ResourceError> {
+        let mut subjects = Vec::new();
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let Some(name) = entry.file_name().to_str().map(ToString::to_string) else {
+                continue;
+            };
+            subjects.push(self.read_subject(&name)?);
+        }
+        subjects.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(subjects)
+    }
+
+    fn read_set(&self, id: &str) -> Result<Set, ResourceError> {
+        let path = self.set_path(id)?;
+        let config = fs::read_to_string(&path).map_err(|error| match error.kind() {
+            ErrorKind::NotFound => ResourceError::NotFound(format!("/sets/{id}")),
+            _ => error.into(),
+        })?;
+        let file: SetFile = toml::from_str(&config)?;
+        let subject = id.split('/').next().unwrap_or_default();
+        let folder = id
+            .rsplit_once('/')
+            .map(|(directory, _)| directory)
+            .filter(|directory| *directory != subject);
+        let title = if file.title.is_empty() {
+            file_stem(&path).replace('-', " ")
+        } else {
+            file.title
+        };
+        let mut set = Set {
+            id: id.to_string(),
+            title,
+            description: file.description,
+            subject: self.read_subject(subject)?,
+            folder: folder.map(ToString::to_string),
+            algorithm: file.algorithm,
+            remix_of: file.remix_of,
+            cards: file.cards,
+        };
+        set.assign_card_ids();
+        Ok(set)
+    }
+
+    /// Every folder below the subject's directory, and the id of every set file.
+    fn walk(&self, subject: &str) -> Result<(Vec<Folder>, Vec<String>), ResourceError> {
+        let mut folders = Vec::new();
+        let mut sets = Vec::new();
+        let mut directories = vec![(subject.to_string(), None::<String>)];
+        while let Some((directory, parent)) = directories.pop() {
+            for entry in fs::read_dir(self.root.join(&directory))? {
+                let entry = entry?;
+                let path = entry.path();
+                let id = format!("{directory}/{}", file_stem(&path));
+                if entry.file_type()?.is_dir() {
+                    folders.push(Folder {
+                        id: id.clone(),
+                        title: file_stem(&path).replace('-', " "),
+                        subject: subject.to_string(),
+                        parent: parent.clone(),
+                    });
+                    directories.push((id.clone(), Some(id)));
+                } else if path
+                    .extension()
+                    .is_some_and(|extension| extension == "toml")
+                    && entry.file_name() != "mod.toml"
+                {
+                    sets.push(id);
+                }
+            }
+        }
+        folders.sort_by(|a, b| a.id.cmp(&b.id));
+        sets.sort();
+        Ok((folders, sets))
+    }
+
+    fn read_sets(&self, subject: &str) -> Result<Vec<Set>, ResourceError> {
+        let (_, sets) = self.walk(subject)?;
+        sets.iter().map(|id| self.read_set(id)).collect()
+    }
+
+    fn read_remixes(&self, id: &str) -> Result<Vec<Set>, ResourceError> {
+        let mut remixes = Vec::new();
+        for subject in self.read_subjects()? {
+            remixes.extend(
+                self.read_sets(&subject.id)?
+                    .into_iter()
+                    .filter(|set| set.remix_of.as_deref() == Some(id)),
+            );
+        }
+        Ok(remixes)
+    }
+
+    fn write_set(&self, set: &Set) -> Result<(), ResourceError> {
+        let path = self.set_path(&set.id)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = SetFile {
+            title: set.title.clone(),
+            description: set.description.clone(),
+            algorithm: set.algorithm,
+            remix_of: set.remix_of.clone(),
+            cards: set.cards.clone(),
+        };
+        fs::write(path, toml::to_string(&file)?)?;
+        Ok(())
+    }
+
+    fn write_folder(&self, folder: &Folder) -> Result<(), ResourceError> {
+        // the same rules as set ids, without the extension
+        let path = self.set_path(&folder.id)?.with_extension("");
+        fs::create_dir_all(path)?;
+        Ok(())
+    }
+
+    fn remove_set(&self, id: &str) -> Result<(), ResourceError> {
+        fs::remove_file(self.set_path(id)?).map_err(|error| match error.kind() {
+            ErrorKind::NotFound => ResourceError::NotFound(format!("/sets/{id}")),
+            _ => error.into(),
+        })
+    }
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+impl Store for FileStore {
+    fn subjects(&self) -> BoxFuture<'_, Result<Vec<Subject>, ResourceError>> {
+        async move { self.read_subjects() }.boxed()
+    }
+
+    fn folders<'a>(
+        &'a self,
+        subject: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Folder>, ResourceError>> {
+        async move { Ok(self.walk(subject)?.0) }.boxed()
+    }
+
+    fn sets<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>> {
+        async move { self.read_sets(subject) }.boxed()
+    }
+
+    fn get_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Set, ResourceError>> {
+        async move { self.read_set(id) }.boxed()
+    }
+
+    fn remixes<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>> {
+        async move { self.read_remixes(id) }.boxed()
+    }
+
+    fn save_set<'a>(&'a self, set: &'a Set) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move { self.write_set(set) }.boxed()
+    }
+
+    fn delete_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move { self.remove_set(id) }.boxed()
+    }
+
+    fn save_folder<'a>(&'a self, folder: &'a Folder) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move { self.write_folder(folder) }.boxed()
+    }
+
+    /// Reviews are learner progress rather than sets, so like the rest of it they
+    /// are kept in the working directory, in `./reviews.jsonl`.
+    fn save_review<'a>(
+        &'a self,
+        review: &'a ReviewLog,
+    ) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move { review.append() }.boxed()
+    }
+
+    fn reviews(&self) -> BoxFuture<'_, Result<Vec<ReviewLog>, ResourceError>> {
+        async move { ReviewLog::load_all() }.boxed()
+    }
+}
+
+/// Keeps everything in memory, for testing handlers without touching disk.
+#[derive(Default)]
+pub struct MemoryStore {
+    subjects: Vec<Subject>,
+    folders: RwLock<Vec<Folder>>,
+    sets: RwLock<HashMap<String, Set>>,
+    reviews: RwLock<Vec<ReviewLog>>,
+}
+
+impl MemoryStore {
+    pub fn new(
+        subjects: Vec<Subject>,
+        folders: Vec<Folder>,
+        sets: impl IntoIterator<Item = Set>,
+    ) -> Self {
+        Self {
+            subjects,
+            folders: RwLock::new(folders),
+            sets: RwLock::new(sets.into_iter().map(|set| (set.id.clone(), set)).collect()),
+            reviews: RwLock::default(),
+        }
+    }
+
+    fn poisoned<T>(_: T) -> ResourceError {
+        ResourceError::Custom("memory store lock was poisoned".to_string())
+    }
+}
+
+impl Store for MemoryStore {
+    fn subjects(&self) -> BoxFuture<'_, Result<Vec<Subject>, ResourceError>> {
+        async move { Ok(self.subjects.clone()) }.boxed()
+    }
+
+    fn folders<'a>(
+        &'a self,
+        subject: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Folder>, ResourceError>> {
+        async move {
+            Ok(self
+                .folders
+                .read()
+                .map_err(Self::poisoned)?
+                .iter()
+                .filter(|folder| folder.subject == subject)
+                .cloned()
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn sets<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>> {
+        async move {
+            let sets = self.sets.read().map_err(Self::poisoned)?;
+            let mut sets = sets
+                .values()
+                .filter(|set| set.subject.id == subject)
+                .cloned()
+                .collect::<Vec<_>>();
+            sets.sort_by(|a, b| a.id.cmp(&b.id));
+            Ok(sets)
+        }
+        .boxed()
+    }
+
+    fn get_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Set, ResourceError>> {
+        async move {
+            self.sets
+                .read()
+                .map_err(Self::poisoned)?
+                .get(id)
+                .cloned()
+                .ok_or_else(|| ResourceError::NotFound(format!("/sets/{id}")))
+        }
+        .boxed()
+    }
+
+    fn remixes<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>> {
+        async move {
+            let sets = self.sets.read().map_err(Self::poisoned)?;
+            let mut remixes = sets
+                .values()
+                .filter(|set| set.remix_of.as_deref() == Some(id))
+                .cloned()
+                .collect::<Vec<_>>();
+            remixes.sort_by(|a, b| a.id.cmp(&b.id));
+            Ok(remixes)
+        }
+        .boxed()
+    }
+
+    fn save_set<'a>(&'a self, set: &'a Set) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move {
+            self.sets
+                .write()
+                .map_err(Self::poisoned)?
+                .insert(set.id.clone(), set.clone());
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn delete_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move {
+            self.sets
+                .write()
+                .map_err(Self::poisoned)?
+                .remove(id)
+                .map(|_| ())
+                .ok_or_else(|| ResourceError::NotFound(format!("/sets/{id}")))
+        }
+        .boxed()
+    }
+
+    fn save_folder<'a>(&'a self, folder: &'a Folder) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move {
+            let mut folders = self.folders.write().map_err(Self::poisoned)?;
+            if !folders.iter().any(|existing| existing.id == folder.id) {
+                folders.push(folder.clone());
+                folders.sort_by(|a, b| a.id.cmp(&b.id));
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn save_review<'a>(
+        &'a self,
+        review: &'a ReviewLog,
+    ) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move {
+            self.reviews
+                .write()
+                .map_err(Self::poisoned)?
+                .push(review.clone());
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn reviews(&self) -> BoxFuture<'_, Result<Vec<ReviewLog>, ResourceError>> {
+        async move { Ok(self.reviews.read().map_err(Self::poisoned)?.clone()) }.boxed()
+    }
+}
+
+/// Subjects and sets for tests to fill a [`MemoryStore`] with.
+#[cfg(test)]
+pub mod fixtures {
+    use crate::data::{Card, Set, Subject};
+
+    pub fn maths() -> Subject {
+        Subject {
+            id: "maths".to_string(),
+            name: "maths".to_string(),
+            color: "red".to_string(),
+        }
+    }
+
+    pub fn geography() -> Subject {
+        Subject {
+            id: "geography".to_string(),
+            name: "geography".to_string(),
+            color: "emerald".to_string(),
+        }
+    }
+
+    /// `maths/algebra`, with the single card `x-plus-x`.
+    pub fn algebra() -> Set {
+        Set {
+            id: "maths/algebra".to_string(),
+            title: "algebra".to_string(),
+            description: String::new(),
+            subject: maths(),
+            folder: None,
+            remix_of: None,
+            algorithm: None,
+            cards: vec![Card {
+                id: "x-plus-x".to_string(),
+                term: "x + x".to_string(),
+                definition: "2x".to_string(),
+            }],
+        }
+    }
+}
+
+impl Store for Database {
+    fn subjects(&self) -> BoxFuture<'_, Result<Vec<Subject>, ResourceError>> {
+        self.fetch_subjects().boxed()
+    }
+
+    fn folders<'a>(
+        &'a self,
+        subject: &'a str,
+    ) -> BoxFuture<'a, Result<Vec<Folder>, ResourceError>> {
+        self.fetch_folders(subject).boxed()
+    }
+
+    fn sets<'a>(&'a self, subject: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>> {
+        self.fetch_sets(subject).boxed()
+    }
+
+    fn get_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Set, ResourceError>> {
+        Self::get_set(self, id).boxed()
+    }
+
+    fn remixes<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Vec<Set>, ResourceError>> {
+        self.fetch_remixes(id).boxed()
+    }
+
+    fn save_set<'a>(&'a self, set: &'a Set) -> BoxFuture<'a, Result<(), ResourceError>> {
+        Self::save_set(self, set).boxed()
+    }
+
+    fn delete_set<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), ResourceError>> {
+        Self::delete_set(self, id).boxed()
+    }
+
+    fn save_folder<'a>(&'a self, folder: &'a Folder) -> BoxFuture<'a, Result<(), ResourceError>> {
+        async move { self.insert_folder(folder).await.map(|_| ()) }.boxed()
+    }
+
+    fn save_review<'a>(
+        &'a self,
+        review: &'a ReviewLog,
+    ) -> BoxFuture<'a, Result<(), ResourceError>> {
+        self.insert_review(review).boxed()
+    }
+
+    fn reviews(&self) -> BoxFuture<'_, Result<Vec<ReviewLog>, ResourceError>> {
+        self.fetch_reviews().boxed()
+    }
+}
@@ -0,0 +1,251 @@
+//! `/study`: studying one or more sets as one session, picked as a list of sets
+//! (`?sets=a,b`, or `?sets=a&sets=b` from checkboxes) or as everything in a
+//! subject (`?subject=`) or folder (`?subject=&folder=`). The session itself is
+//! kept on the server (see [`crate::session`]) and answered one card at a time.
+use crate::components;
+use crate::data::{Card, Direction, Folder, Query, Rating, ResourceError, Set, Side};
+use crate::grading::normalise;
+use crate::scheduler::{self, Progress};
+use crate::session::{Item, Sessions, StudySession};
+use crate::store::Store;
+use html_builder::prelude::*;
+use std::collections::HashSet;
+
+/// Which sets a session is made of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scope {
+    Sets(Vec<String>),
+    /// Every set in the subject or, if a folder is given, in that folder and the
+    /// folders inside it.
+    Subject {
+        subject: String,
+        folder: Option<String>,
+    },
+}
+
+impl Scope {
+    /// Reads the scope from a raw query string, or `None` if it doesn't name one.
+    pub fn from_query(query: &str) -> Option<Self> {
+        let pairs = url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect::<Vec<_>>();
+        let get = |key: &str| {
+            pairs
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.clone())
+        };
+        let sets = pairs
+            .iter()
+            .filter(|(name, _)| name == "sets")
+            .flat_map(|(_, value)| value.split(','))
+            .filter(|id| !id.is_empty())
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if !sets.is_empty() {
+            return Some(Self::Sets(sets));
+        }
+        get("subject").map(|subject| Self::Subject {
+            subject,
+            folder: get("folder").filter(|folder| !folder.is_empty()),
+        })
+    }
+
+    /// The query string [`Scope::from_query`] reads back as this scope.
+    pub fn to_query(&self) -> String {
+        let mut query = url::form_urlencoded::Serializer::new(String::new());
+        match self {
+            Self::Sets(sets) => {
+                query.append_pair("sets", &sets.join(","));
+            }
+            Self::Subject { subject, folder } => {
+                query.append_pair("subject", subject);
+                if let Some(folder) = folder {
+                    query.append_pair("folder", folder);
+                }
+            }
+        }
+        query.finish()
+    }
+
+    pub async fn sets(&self, store: &dyn Store) -> Result<Vec<Set>, ResourceError> {
+        match self {
+            Self::Sets(ids) => {
+                let mut sets = Vec::new();
+                for id in ids {
+                    sets.push(store.get_set(id).await?);
+                }
+                Ok(sets)
+            }
+            Self::Subject {
+                subject,
+                folder: None,
+            } => store.sets(subject).await,
+            Self::Subject {
+                subject,
+                folder: Some(folder),
+            } => {
+                let folders = store.folders(subject).await?;
+                Ok(store
+                    .sets(subject)
+                    .await?
+                    .into_iter()
+                    .filter(|set| {
+                        set.folder.as_deref().is_some_and(|parent| {
+                            Folder::breadcrumbs(&folders, parent)
+                                .iter()
+                                .any(|ancestor| &ancestor.id == folder)
+                        })
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Every set's items, each with the id of its set, keeping only the first of any
+/// cards that are the same apart from case and punctuation.
+pub fn combined_items(sets: &[Set]) -> Vec<(String, Card)> {
+    let mut seen = HashSet::new();
+    sets.iter()
+        .flat_map(|set| set.items().into_iter().map(|card| (set.id.clone(), card)))
+        .filter(|(_, card)| seen.insert((normalise(&card.term), normalise(&card.definition))))
+        .collect()
+}
+
+/// The session's current card, skipping any that have been deleted since the
+/// session started.
+pub async fn study_card(
+    mut session: StudySession,
+    store: &dyn Store,
+) -> Result<Div, ResourceError> {
+    while let Some(item) = session.current.clone() {
+        if let Some(card) = find_card(&item, store).await? {
+            return Ok(components::study_card(&session, Some(&card)));
+        }
+        let id = session.id.clone();
+        session = Sessions::update(|sessions| {
+            let session = sessions.0.get_mut(&id)?;
+            session.skip(scheduler::now());
+            Some(session.clone())
+        })?
+        .ok_or_else(|| ResourceError::NotFound(format!("/session/{id}")))?;
+    }
+    let Some(cram) = &session.cram else {
+        return Ok(components::study_card(&session, None));
+    };
+    let mut weakest = Vec::new();
+    for card in cram.weakest() {
+        if let Some(found) = find_card(&card.item, store).await? {
+            weakest.push((found, card));
+        }
+    }
+    Ok(components::cram_summary(&session, &weakest))
+}
+
+async fn find_card(item: &Item, store: &dyn Store) -> Result<Option<Card>, ResourceError> {
+    match store.get_set(&item.set).await {
+        Ok(set) => Ok(set.items().into_iter().find(|card| card.id == item.card)),
+        Err(ResourceError::NotFound(_)) => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Resumes the learner's unfinished session over the scope, or starts one. `cram`
+/// is the streak each card needs if the session is a cram.
+pub async fn start(
+    scope: &Scope,
+    direction: Direction,
+    cram: Option<u32>,
+    store: &dyn Store,
+) -> Result<StudySession, ResourceError> {
+    let sets = scope.sets(store).await?;
+    let title = match scope {
+        Scope::Sets(_) => sets
+            .iter()
+            .map(|set| set.title.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Scope::Subject {
+            folder: Some(folder),
+            ..
+        } => folder.clone(),
+        Scope::Subject { subject, .. } => subject.clone(),
+    };
+    let title = if cram.is_some() {
+        format!("cram: {title}")
+    } else {
+        title
+    };
+    Sessions::resume_or_start(title, scope, direction, cram, &sets)
+}
+
+/// `/study` and `/session/{id}`.
+pub async fn page(session: StudySession, store: &dyn Store) -> Result<Html, ResourceError> {
+    Ok(components::page(
+        format!("{} - flopcards", session.title),
+        body()
+            .child(h1("Study"))
+            .child(p(&session.title))
+            .child(study_card(session, store).await?),
+    ))
+}
+
+/// Handles `POST /sets/{set}/cards/{card}/review?rating=&shown=`, given
+/// `{set}/cards/{card}`, rating a card outside of a session. `front=definition`
+/// rates the card the other way round. Answers with when the card is next due.
+pub async fn review(path: &str, query: &Query, store: &dyn Store) -> Result<Div, ResourceError> {
+    let (set_id, card) = path
+        .rsplit_once("/cards/")
+        .ok_or_else(|| ResourceError::NotFound(format!("/sets/{path}/review")))?;
+    let rating = query.parse::<Rating>("rating")?;
+    let shown = query.parse("shown")?;
+    let front = query.parse("front").unwrap_or(Side::Term);
+    let set = store.get_set(set_id).await?;
+    let state = scheduler::record_review(&set, card, front, rating, shown, store).await?;
+    Ok(div()
+        .id("review")
+        .child(p(format!("next review in {} days", state.interval.round()))))
+}
+
+/// Handles `POST /session/{id}/answer?set=&card=&front=&rating=&shown=`, recording
+/// the rating with the scheduler if it is the card's first this session (and the
+/// session isn't a cram), and returns the next card. The card must be the one the
+/// session is showing, so that answering twice (from two tabs, or a double click)
+/// can't rate the card after it.
+pub async fn answer(id: &str, query: &Query, store: &dyn Store) -> Result<Div, ResourceError> {
+    let rating = query.parse::<Rating>("rating")?;
+    let shown = query.parse("shown")?;
+    let item = Item::from_query(query)?;
+    let set = store.get_set(&item.set).await?;
+    let (session, review) = Sessions::update(|sessions| {
+        let session = sessions
+            .0
+            .get_mut(id)
+            .ok_or_else(|| ResourceError::NotFound(format!("/session/{id}")))?;
+        if session.current.as_ref() != Some(&item) {
+            return Err(ResourceError::Custom(
+                "that card has already been answered".to_string(),
+            ));
+        }
+        let progress = Progress::load()?;
+        let was_new = set
+            .items()
+            .iter()
+            .find(|card| card.id == item.card)
+            .is_none_or(|card| progress.get(&set.id, &card.review_id(item.front)).is_new());
+        let review = if session.should_record() {
+            Some(scheduler::schedule_review(
+                &set, &item.card, item.front, rating, shown,
+            )?)
+        } else {
+            None
+        };
+        session.answer(rating, was_new, scheduler::now())?;
+        Ok((session.clone(), review))
+    })??;
+    if let Some(review) = review {
+        review.log(store).await?;
+    }
+    study_card(session, store).await
+}
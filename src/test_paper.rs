@@ -0,0 +1,158 @@
+//! `/sets/{id}/test`: a printable test made from a set, in written,
+//! multiple-choice, true/false and matching sections, with its answer key at
+//! `/sets/{id}/test/answers`. The same count and seed always make the same test.
+use crate::components;
+use crate::data::{Card, Query, ResourceError, Set, Side};
+use crate::quiz::{self, Question};
+use crate::random::Rng;
+use crate::scheduler;
+use crate::store::Store;
+use html_builder::prelude::*;
+
+/// How many questions a test has if `?count=` isn't given.
+pub const DEFAULT_COUNT: usize = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrueFalse {
+    pub card: Card,
+    /// The definition printed with the term, which may belong to another card.
+    pub shown: String,
+    pub is_true: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Matching {
+    pub terms: Vec<Card>,
+    /// The definitions of `terms`, shuffled.
+    pub definitions: Vec<String>,
+    /// For each term, the index of its definition.
+    pub answers: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TestPaper {
+    pub set: Set,
+    pub count: usize,
+    pub seed: u64,
+    pub written: Vec<Card>,
+    pub multiple_choice: Vec<Question>,
+    pub true_false: Vec<TrueFalse>,
+    pub matching: Matching,
+}
+
+impl TestPaper {
+    /// Picks `count` of the set's items (or all of them, if there are fewer) and
+    /// deals them between the sections as evenly as it can.
+    pub fn new(set: Set, count: usize, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let pool = set.items();
+        let mut items = pool.clone();
+        rng.shuffle(&mut items);
+        items.truncate(count);
+        let quarter = items.len() / 4;
+        // matching needs at least two pairs to be worth printing
+        let matching = if quarter >= 2 { quarter } else { 0 };
+        let mut items = items.into_iter();
+        let multiple_choice = items
+            .by_ref()
+            .take(quarter)
+            .map(|card| quiz::question(&card, &pool, Side::Definition, seed))
+            .collect();
+        let true_false = items
+            .by_ref()
+            .take(quarter)
+            .map(|card| true_false(card, &pool, &mut rng))
+            .collect();
+        let matching = self::matching(items.by_ref().take(matching).collect(), &mut rng);
+        Self {
+            written: items.collect(),
+            multiple_choice,
+            true_false,
+            matching,
+            count,
+            seed,
+            set,
+        }
+    }
+}
+
+/// Pairs the term with its own definition or, half the time, a similar one from
+/// another card. Cards with nothing similar to swap in are always true.
+fn true_false(card: Card, pool: &[Card], rng: &mut Rng) -> TrueFalse {
+    let definition = card.definition.trim().to_string();
+    let shown = if rng.below(2) == 0 {
+        None
+    } else {
+        quiz::distractors(
+            &definition,
+            pool.iter()
+                .filter(|other| other.id != card.id)
+                .map(|other| other.definition.as_str()),
+            1,
+            rng,
+        )
+        .pop()
+    };
+    TrueFalse {
+        is_true: shown.is_none(),
+        shown: shown.unwrap_or(definition),
+        card,
+    }
+}
+
+fn matching(terms: Vec<Card>, rng: &mut Rng) -> Matching {
+    let mut order = (0..terms.len()).collect::<Vec<_>>();
+    rng.shuffle(&mut order);
+    let definitions = order
+        .iter()
+        .map(|index| terms[*index].definition.trim().to_string())
+        .collect();
+    let answers = (0..terms.len())
+        .map(|term| {
+            order
+                .iter()
+                .position(|index| *index == term)
+                .unwrap_or(term)
+        })
+        .collect();
+    Matching {
+        terms,
+        definitions,
+        answers,
+    }
+}
+
+async fn paper(id: &str, query: &Query, store: &dyn Store) -> Result<TestPaper, ResourceError> {
+    let set = store.get_set(id).await?;
+    let count = if query.0.contains_key("count") {
+        query.parse("count")?
+    } else {
+        DEFAULT_COUNT
+    };
+    let seed = if query.0.contains_key("seed") {
+        query.parse("seed")?
+    } else {
+        scheduler::now_millis().unsigned_abs()
+    };
+    Ok(TestPaper::new(set, count, seed))
+}
+
+pub async fn page(id: &str, query: &Query, store: &dyn Store) -> Result<Html, ResourceError> {
+    let paper = paper(id, query, store).await?;
+    Ok(components::page(
+        format!("{} - test - flopcards", paper.set.title),
+        body().child(components::test_paper(&paper)),
+    ))
+}
+
+pub async fn answers_page(
+    id: &str,
+    query: &Query,
+    store: &dyn Store,
+) -> Result<Html, ResourceError> {
+    let paper = paper(id, query, store).await?;
+    Ok(components::page(
+        format!("{} - answers - flopcards", paper.set.title),
+        body().child(components::test_answer_key(&paper)),
+    ))
+}
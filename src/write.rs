@@ -0,0 +1,65 @@
+//! `/sets/{id}/write`: type the answer instead of flipping the card, and have it
+//! marked and rated automatically.
+use crate::components;
+use crate::data::{body_to_string, Query, ResourceError, Side};
+use crate::grading;
+use crate::scheduler::{self, next_card};
+use crate::store::Store;
+use html_builder::prelude::*;
+use hyper::Request;
+
+/// Which side the learner types, from `?answer=`; the definition unless told otherwise.
+pub fn answer_side(query: &Query) -> Result<Side, ResourceError> {
+    query
+        .get("answer")
+        .map_or(Ok(Side::Definition), |side| side.parse())
+}
+
+pub async fn page(id: &str, query: &Query, store: &dyn Store) -> Result<Html, ResourceError> {
+    let set = store.get_set(id).await?;
+    let answer = answer_side(query)?;
+    Ok(components::page(
+        format!("{} - write - flopcards", set.title),
+        body().child(h1("Write")).child(components::write_prompt(
+            &set.id,
+            next_card(&set, answer.other())?.as_ref(),
+            answer,
+        )),
+    ))
+}
+
+/// `/view/write?set={id}`: the next card to answer.
+pub async fn prompt(query: &Query, store: &dyn Store) -> Result<Div, ResourceError> {
+    let set = store.get_set(&query.get("set")?).await?;
+    let answer = answer_side(query)?;
+    Ok(components::write_prompt(
+        &set.id,
+        next_card(&set, answer.other())?.as_ref(),
+        answer,
+    ))
+}
+
+/// Marks a `POST /sets/{set}/cards/{card}/write`, given `{set}/cards/{card}`, and
+/// records the rating it earned.
+pub async fn answer(
+    request: Request<hyper::body::Incoming>,
+    path: &str,
+    store: &dyn Store,
+) -> Result<Div, ResourceError> {
+    let (set_id, card_id) = path
+        .rsplit_once("/cards/")
+        .ok_or_else(|| ResourceError::NotFound(format!("/sets/{path}/write")))?;
+    let query = Query::from_request(&request);
+    let answer = answer_side(&query)?;
+    let shown = query.parse("shown")?;
+    let form = Query::from_str(&body_to_string(request).await?);
+    let set = store.get_set(set_id).await?;
+    let card = set
+        .items()
+        .into_iter()
+        .find(|card| card.id == card_id)
+        .ok_or_else(|| ResourceError::NotFound(format!("/sets/{path}/write")))?;
+    let grade = grading::grade(card.side(answer), &form.get("answer")?);
+    scheduler::record_review(&set, card_id, answer.other(), grade.rating, shown, store).await?;
+    Ok(components::write_feedback(&set.id, &card, &grade, answer))
+}